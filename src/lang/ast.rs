@@ -1,16 +1,61 @@
-use std::fmt;
+use std::{fmt, rc::Rc};
 
-#[derive(PartialEq, Clone)]
+use crate::lang::{
+    exec::Nested,
+    token::{Span, Token, TokenKind},
+    types::error::{RiskError, RiskResult},
+};
+
+#[derive(Clone)]
 pub enum AstNode {
-    Leaf(String),
-    AST(Vec<AstNode>),
+    Leaf(String, Span),
+    Str(String, Span),
+    // #:name, holding the name without the #:
+    Keyword(String, Span),
+    // Shared, as code is cloned whenever it's evaluated
+    AST(Rc<[AstNode]>, Span),
+}
+
+impl AstNode {
+    pub fn leaf(s: &str) -> AstNode {
+        AstNode::Leaf(s.to_string(), Span::default())
+    }
+
+    pub fn list(nodes: Vec<AstNode>) -> AstNode {
+        AstNode::AST(nodes.into(), Span::default())
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            AstNode::Leaf(_, span)
+            | AstNode::Str(_, span)
+            | AstNode::Keyword(_, span)
+            | AstNode::AST(_, span) => span,
+        }
+    }
+}
+
+// Nodes are equal when they have the same shape, regardless of where in the
+// source they came from
+impl PartialEq for AstNode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AstNode::Leaf(l, _), AstNode::Leaf(r, _)) => l == r,
+            (AstNode::Str(l, _), AstNode::Str(r, _)) => l == r,
+            (AstNode::Keyword(l, _), AstNode::Keyword(r, _)) => l == r,
+            (AstNode::AST(l, _), AstNode::AST(r, _)) => l == r,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Debug for AstNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            AstNode::Leaf(s) => s.fmt(f),
-            AstNode::AST(nodes) => {
+            AstNode::Leaf(s, _) => s.fmt(f),
+            AstNode::Str(s, _) => write!(f, "{:?}", s),
+            AstNode::Keyword(k, _) => write!(f, "#:{}", k),
+            AstNode::AST(nodes, _) => {
                 write!(f, "[")?;
                 for (i, node) in nodes.iter().enumerate() {
                     let _ = node.fmt(f);
@@ -27,126 +72,246 @@ impl fmt::Debug for AstNode {
     }
 }
 
-fn find_closing_paren(tokens: Vec<String>) -> Option<usize> {
-    let (closing, _, found) = tokens.iter().fold((0, 0, false), |acc, token| {
-        let (mut index, mut opening_count, mut found) = acc;
-        if found {
-            return acc;
-        }
-
-        if token == "(" {
-            opening_count += 1;
-        } else if token == ")" {
-            opening_count -= 1;
-        }
-
-        if opening_count != 0 {
-            index += 1
-        } else {
-            found = true
-        }
+pub fn new(tokens: Vec<Token>) -> RiskResult<Vec<AstNode>> {
+    let mut tokens = tokens.into_iter();
+    let mut tree = Vec::new();
 
-        (index, opening_count, found)
-    });
+    while let Some(token) = tokens.next() {
+        tree.push(expr(token, &mut tokens)?);
+    }
 
-    if found { Some(closing) } else { None }
+    Ok(tree)
 }
 
-pub fn new(tokens: Vec<String>) -> Vec<AstNode> {
-    match tokens[..] {
-        [] => Vec::new(),
-        _ => match tokens[0].as_str() {
-            ")" => panic!("Syntax error, unexpected ')'"),
-            "(" => {
-                let closing =
-                    find_closing_paren(tokens.clone()).expect("closing paren to be found");
+// Reads the expression starting at first, taking as many more tokens as it
+// needs from rest
+fn expr(first: Token, rest: &mut impl Iterator<Item = Token>) -> RiskResult<AstNode> {
+    let _nested = Nested::enter(&first.span)?;
 
-                let mut tree: Vec<AstNode> = vec![AstNode::AST(new(tokens[1..closing].to_vec()))];
-                tree.extend(new(tokens[closing + 1..].to_vec()));
+    match first.kind {
+        TokenKind::Close => Err(RiskError::Syntax(
+            "unexpected ')'".to_string(),
+            Some(first.span),
+        )),
+        TokenKind::Open => {
+            let mut nodes = Vec::new();
 
-                tree
-            }
-            _ => {
-                let (first, rest) = tokens.split_first().expect("to have at least one value");
-                let mut parsed = vec![AstNode::Leaf(first.to_string())];
-                parsed.extend(new(rest.to_vec()));
-                parsed
+            loop {
+                match rest.next() {
+                    Some(token) if token.kind == TokenKind::Close => {
+                        return Ok(AstNode::AST(nodes.into(), first.span.to(&token.span)));
+                    }
+                    Some(token) => nodes.push(expr(token, rest)?),
+                    None => {
+                        return Err(RiskError::Syntax(
+                            "missing closing ')'".to_string(),
+                            Some(first.span),
+                        ));
+                    }
+                }
             }
-        },
+        }
+        TokenKind::Quote
+        | TokenKind::Quasiquote
+        | TokenKind::Unquote
+        | TokenKind::UnquoteSplicing => {
+            // 'expr is read as (quote expr), and likewise for the others
+            let form = match first.kind {
+                TokenKind::Quote => "quote",
+                TokenKind::Quasiquote => "quasiquote",
+                TokenKind::Unquote => "unquote",
+                _ => "unquote-splicing",
+            };
+
+            let quoted = match rest.next() {
+                Some(token) if token.kind != TokenKind::Close => expr(token, rest)?,
+                _ => {
+                    return Err(RiskError::Syntax(
+                        format!("expected an expression after {}", first.text),
+                        Some(first.span),
+                    ));
+                }
+            };
+
+            let span = first.span.to(quoted.span());
+            Ok(AstNode::AST(
+                vec![AstNode::Leaf(form.to_string(), first.span), quoted].into(),
+                span,
+            ))
+        }
+        TokenKind::Str => Ok(AstNode::Str(first.text, first.span)),
+        TokenKind::Keyword => Ok(AstNode::Keyword(first.text[2..].to_string(), first.span)),
+        TokenKind::Atom => Ok(AstNode::Leaf(first.text, first.span)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lang::token::tokenize;
+
+    fn tokens(texts: &[&str]) -> Vec<Token> {
+        texts
+            .iter()
+            .map(|t| Token {
+                kind: match *t {
+                    "(" => TokenKind::Open,
+                    ")" => TokenKind::Close,
+                    "'" => TokenKind::Quote,
+                    "`" => TokenKind::Quasiquote,
+                    "," => TokenKind::Unquote,
+                    ",@" => TokenKind::UnquoteSplicing,
+                    _ => TokenKind::Atom,
+                },
+                text: t.to_string(),
+                span: Span::default(),
+            })
+            .collect()
+    }
 
     #[test]
-    fn find_closing_paren_doesnt_on_mismatch_closing() {
+    fn new_ast_works_on_well_formed() {
         assert_eq!(
-            find_closing_paren(["(", "(", ")"].iter().map(|s| s.to_string()).collect()),
-            None
+            new(tokens(&[
+                "(", "sym", ")", "(", "lambda", "(", "x", ")", "x", ")"
+            ]))
+            .unwrap(),
+            vec![
+                AstNode::list(vec![AstNode::leaf("sym")]),
+                AstNode::list(vec![
+                    AstNode::leaf("lambda"),
+                    AstNode::list(vec![AstNode::leaf("x")]),
+                    AstNode::leaf("x")
+                ])
+            ]
         )
     }
 
     #[test]
-    fn find_closing_paren_does_on_excess_closing() {
-        assert_eq!(
-            find_closing_paren(["(", ")", ")"].iter().map(|s| s.to_string()).collect()),
-            Some(1)
-        )
+    fn new_ast_works_for_single_sym() {
+        assert_eq!(new(tokens(&["sym"])).unwrap(), vec![AstNode::leaf("sym")])
+    }
+
+    #[test]
+    fn new_ast_fails_for_syntax_error_mismatched_parens() {
+        assert!(matches!(
+            new(tokens(&["(", "(", "(", "(", ")", ")", ")"])),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn new_ast_fails_for_syntax_error_excess_closing_parens() {
+        assert!(matches!(
+            new(tokens(&["(", ")", ")", ")"])),
+            Err(RiskError::Syntax(..))
+        ));
     }
 
     #[test]
-    fn find_closing_paren_does_on_well_formed() {
+    fn new_ast_records_spans() {
+        let parsed = new(tokenize("(f\n  x) y").unwrap()).unwrap();
+
+        match &parsed[0] {
+            AstNode::AST(nodes, span) => {
+                assert_eq!((span.line, span.col, span.start, span.end), (1, 1, 0, 7));
+                assert_eq!(nodes[1].span().line, 2);
+                assert_eq!(nodes[1].span().col, 3);
+            }
+            _ => panic!("expected a list"),
+        }
+        assert_eq!(parsed[1].span().start, 8);
+    }
+
+    #[test]
+    fn new_ast_syntax_errors_point_at_token() {
+        match new(tokenize("(f x)\n  )").unwrap()) {
+            Err(RiskError::Syntax(_, Some(span))) => assert_eq!((span.line, span.col), (2, 3)),
+            _ => panic!("expected a syntax error with a span"),
+        }
+    }
+
+    #[test]
+    fn new_ast_keeps_strings_distinct_from_symbols() {
         assert_eq!(
-            find_closing_paren(
-                ["(", "sym", "other", "token", ")"]
-                    .iter()
-                    .map(|s| s.to_string())
-                    .collect()
-            ),
-            Some(4)
+            new(tokenize(r#"(f "x" x)"#).unwrap()).unwrap(),
+            vec![AstNode::list(vec![
+                AstNode::leaf("f"),
+                AstNode::Str("x".to_string(), Span::default()),
+                AstNode::leaf("x")
+            ])]
         )
     }
 
     #[test]
-    fn new_ast_works_on_well_formed() {
+    fn new_ast_expands_quote_shorthand() {
         assert_eq!(
-            new(["(", "sym", ")", "(", "lambda", "(", "x", ")", "x", ")"]
-                .iter()
-                .map(|s| s.to_string())
-                .collect()),
+            new(tokens(&["'", "(", "a", "'", "b", ")", "c"])).unwrap(),
             vec![
-                AstNode::AST(vec![AstNode::Leaf("sym".to_string())]),
-                AstNode::AST(vec![
-                    AstNode::Leaf("lambda".to_string()),
-                    AstNode::AST(vec![AstNode::Leaf("x".to_string())]),
-                    AstNode::Leaf("x".to_string())
-                ])
+                AstNode::list(vec![
+                    AstNode::leaf("quote"),
+                    AstNode::list(vec![
+                        AstNode::leaf("a"),
+                        AstNode::list(vec![AstNode::leaf("quote"), AstNode::leaf("b")])
+                    ])
+                ]),
+                AstNode::leaf("c")
             ]
-        )
+        );
+        assert_eq!(
+            new(tokens(&["'", "'", "a"])).unwrap(),
+            vec![AstNode::list(vec![
+                AstNode::leaf("quote"),
+                AstNode::list(vec![AstNode::leaf("quote"), AstNode::leaf("a")])
+            ])]
+        );
     }
 
     #[test]
-    fn new_ast_works_for_single_sym() {
+    fn new_ast_quote_needs_an_expression() {
+        assert!(matches!(new(tokens(&["'"])), Err(RiskError::Syntax(..))));
+        assert!(matches!(
+            new(tokens(&["(", "'", ")"])),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn new_ast_expands_quasiquote_shorthand() {
         assert_eq!(
-            new(["sym"].iter().map(|s| s.to_string()).collect()),
-            vec![AstNode::Leaf("sym".to_string())]
-        )
+            new(tokens(&["`", "(", "a", ",", "b", ",@", "c", ")"])).unwrap(),
+            vec![AstNode::list(vec![
+                AstNode::leaf("quasiquote"),
+                AstNode::list(vec![
+                    AstNode::leaf("a"),
+                    AstNode::list(vec![AstNode::leaf("unquote"), AstNode::leaf("b")]),
+                    AstNode::list(vec![AstNode::leaf("unquote-splicing"), AstNode::leaf("c")])
+                ])
+            ])]
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn new_ast_fails_for_syntax_error_mismatched_parens() {
-        new(["(", "(", "(", "(", ")", ")", ")"]
-            .iter()
-            .map(|s| s.to_string())
-            .collect());
+    fn new_ast_reads_large_inputs() {
+        let long_list = format!("({})", "x ".repeat(100_000));
+        match &new(tokenize(&long_list).unwrap()).unwrap()[..] {
+            [AstNode::AST(nodes, _)] => assert_eq!(nodes.len(), 100_000),
+            _ => panic!("expected a single list"),
+        }
+
+        let many_forms = "(define (f x) (if (< x 1) 'done (f (- x 1))))\n".repeat(10_000);
+        assert_eq!(new(tokenize(&many_forms).unwrap()).unwrap().len(), 10_000);
     }
 
     #[test]
-    #[should_panic]
-    fn new_ast_fails_for_syntax_error_excess_closing_parens() {
-        new(["(", ")", ")", ")"].iter().map(|s| s.to_string()).collect());
+    fn new_ast_reads_keywords() {
+        assert_eq!(
+            new(tokenize("(f #:timeout 30)").unwrap()).unwrap(),
+            vec![AstNode::list(vec![
+                AstNode::leaf("f"),
+                AstNode::Keyword("timeout".to_string(), Span::default()),
+                AstNode::leaf("30")
+            ])]
+        )
     }
 }
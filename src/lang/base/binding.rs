@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+
+use crate::lang::{
+    ast::AstNode,
+    base::{check_min_arity, eval_arg, tail_sequence},
+    exec::Tail,
+    scope::Scope,
+    types::{
+        error::{RiskError, RiskResult},
+        result::Result,
+        userfunc::FnDef,
+    },
+};
+
+// Reads ((name init) ...) into its names and init expressions
+fn bindings(node: &AstNode, form: &str, unique: bool) -> RiskResult<Vec<(String, AstNode)>> {
+    let malformed = |span| {
+        RiskError::Syntax(
+            format!("<{}> bindings must be a list of (name expr) pairs", form),
+            Some(span),
+        )
+    };
+
+    let pairs = match node {
+        AstNode::AST(pairs, _) => pairs,
+        _ => return Err(malformed(node.span().clone())),
+    };
+
+    let mut seen = HashSet::new();
+    pairs
+        .iter()
+        .map(|pair| match pair {
+            AstNode::AST(parts, span) => match &parts[..] {
+                [AstNode::Leaf(name, _), init] => {
+                    if unique && !seen.insert(name.to_string()) {
+                        return Err(RiskError::Syntax(
+                            format!("<{}> cannot bind {} more than once", form, name),
+                            Some(span.clone()),
+                        ));
+                    }
+
+                    Ok((name.to_string(), init.clone()))
+                }
+                _ => Err(malformed(span.clone())),
+            },
+            _ => Err(malformed(pair.span().clone())),
+        })
+        .collect()
+}
+
+/*
+* Binds names for the duration of its body. Every init is evaluated in the
+* enclosing scope before any name is bound
+*
+* (let ((x 1) (y 2)) (+ x y)) => 3
+*
+* Named let binds a function calling the body too, which allows looping
+*
+* (let loop ((i 0) (acc '())) (if (= i 3) acc (loop (+ i 1) (cons i acc))))
+*   => (2 1 0)
+*/
+pub fn letdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_min_arity("let", &args, 1)?;
+
+    match &args[..] {
+        [] => unreachable!("arity was checked"),
+        [AstNode::Leaf(name, _), binds, body @ ..] => {
+            let binds = bindings(binds, "let", true)?;
+            let mut values = Vec::new();
+            for (_, init) in binds.iter() {
+                values.push(eval_arg(init, scope)?);
+            }
+
+            let mut loop_scope = scope.child();
+            let f = FnDef::new(
+                binds.into_iter().map(|(n, _)| n).collect(),
+                body.to_vec(),
+                loop_scope.clone(),
+            )
+            .named(name);
+            loop_scope.define(name, Result::FnDef(f.clone()));
+
+            f.prepare(values)
+        }
+        [binds, body @ ..] => {
+            let binds = bindings(binds, "let", true)?;
+            let mut local_scope = scope.child();
+            for (name, init) in binds.iter() {
+                local_scope.define(name, eval_arg(init, scope)?);
+            }
+
+            tail_sequence(body, &mut local_scope)
+        }
+    }
+}
+
+/*
+* Like let, but each init sees the names bound before it
+*
+* (let* ((x 1) (y (+ x 1))) y) => 2
+*/
+pub fn letstardef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_min_arity("let*", &args, 1)?;
+    let (binds, body) = args.split_first().expect("arity was checked");
+
+    let mut local_scope = scope.child();
+    for (name, init) in bindings(binds, "let*", false)? {
+        let value = eval_arg(&init, &mut local_scope)?;
+        local_scope = local_scope.child();
+        local_scope.define(&name, value);
+    }
+
+    tail_sequence(body, &mut local_scope)
+}
+
+/*
+* Like let, but every init is evaluated in the scope being created, so
+* functions bound by it can refer to each other
+*
+* (letrec ((even? (lambda (n) (if (= n 0) #t (odd? (- n 1)))))
+*          (odd? (lambda (n) (if (= n 0) #f (even? (- n 1))))))
+*   (even? 10)) => #t
+*/
+pub fn letrecdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_min_arity("letrec", &args, 1)?;
+    let (binds, body) = args.split_first().expect("arity was checked");
+
+    let mut local_scope = scope.child();
+    for (name, init) in bindings(binds, "letrec", true)? {
+        let value = eval_arg(&init, &mut local_scope)?;
+        local_scope.define(&name, value);
+    }
+
+    tail_sequence(body, &mut local_scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::{eval, shows};
+
+    #[test]
+    fn let_binds_for_its_body() {
+        assert_eq!(shows("(let ((x 1) (y 2)) (+ x y))"), "3");
+        assert_eq!(shows("(let () 1 2)"), "2");
+        assert_eq!(shows("(let ((x 1)))"), "None");
+    }
+
+    #[test]
+    fn let_inits_see_the_enclosing_scope() {
+        assert_eq!(shows("(define x 10) (let ((x 1) (y x)) y)"), "10");
+    }
+
+    #[test]
+    fn let_does_not_leak_bindings() {
+        assert!(matches!(
+            eval("(let ((x 1)) x) x"),
+            Err(RiskError::Unbound(..))
+        ));
+        assert!(matches!(
+            eval("(let () (define z 1)) z"),
+            Err(RiskError::Unbound(..))
+        ));
+        assert_eq!(shows("(define x 5) (let ((x 1)) x) x"), "5");
+    }
+
+    #[test]
+    fn let_rejects_malformed_bindings() {
+        assert!(matches!(eval("(let)"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(let (x) x)"), Err(RiskError::Syntax(..))));
+        assert!(matches!(eval("(let ((x)) x)"), Err(RiskError::Syntax(..))));
+        assert!(matches!(
+            eval("(let ((x 1) (x 2)) x)"),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn named_let_loops() {
+        assert_eq!(
+            shows("(let loop ((i 0) (acc '())) (if (= i 3) acc (loop (+ i 1) (cons i acc))))"),
+            "(2 1 0)"
+        );
+        assert_eq!(shows("(let f ((n 3)) (define m (* n 2)) m)"), "6");
+        assert!(matches!(
+            eval("(let f ((n 3)) n) f"),
+            Err(RiskError::Unbound(..))
+        ));
+    }
+
+    #[test]
+    fn let_star_binds_sequentially() {
+        assert_eq!(shows("(let* ((x 1) (y (+ x 1))) (* x y))"), "2");
+        assert_eq!(shows("(let* ((x 1) (x (+ x 1))) x)"), "2");
+        assert_eq!(shows("(let* ((x 1) (f (lambda () x)) (x 2)) (f))"), "1");
+        assert_eq!(shows("(let* () 4)"), "4");
+    }
+
+    #[test]
+    fn letrec_allows_mutual_recursion() {
+        assert_eq!(
+            shows(
+                "(letrec ((ev? (lambda (n) (if (= n 0) #t (od? (- n 1)))))
+                          (od? (lambda (n) (if (= n 0) #f (ev? (- n 1))))))
+                   (ev? 10))"
+            ),
+            "#t"
+        );
+        assert!(matches!(
+            eval("(letrec ((a b) (b 1)) a)"),
+            Err(RiskError::Unbound(..))
+        ));
+    }
+}
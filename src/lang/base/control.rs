@@ -0,0 +1,365 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::lang::{
+    ast::AstNode,
+    base::{check_arity, check_arity_range, check_min_arity, eval_arg, eval_args, quote::code},
+    exec::{self, Tail},
+    scope::Scope,
+    trace,
+    types::{
+        error::{RiskError, RiskResult},
+        pair,
+        result::Result,
+    },
+};
+
+/*
+* Calls a function with the given arguments followed by every item of a list.
+* Keywords among them pass the value after them by keyword, as in a call
+*
+* (apply + '(1 2 3)) => 6
+* (apply list 1 2 '(3 4)) => (1 2 3 4)
+* (apply (lambda (a #:b 0) (- a b)) '(5 #:b 2)) => 3
+*/
+pub fn applydef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_min_arity("apply", &args, 2)?;
+    let (f, rest) = args.split_first().expect("arity was checked");
+    let (list, spread) = rest.split_last().expect("arity was checked");
+
+    let f = eval_arg(f, scope)?;
+    let mut values = eval_args(spread, scope)?;
+    let tail = eval_arg(list, scope)?;
+    values.extend(pair::to_vec(&tail).ok_or_else(|| {
+        RiskError::Type(
+            format!(
+                "<apply> expected a list as its last argument, received {}",
+                tail
+            ),
+            Some(list.span().clone()),
+        )
+    })?);
+
+    exec::apply_tail(f, values, scope)
+}
+
+// Evaluates an argument which has to be an environment
+fn environment_arg(name: &str, arg: &AstNode, scope: &mut Scope) -> RiskResult<Scope> {
+    match eval_arg(arg, scope)? {
+        Result::Environment(env) => Ok(env),
+        value => Err(RiskError::Type(
+            format!("<{}> expected an environment, received {}", name, value),
+            Some(arg.span().clone()),
+        )),
+    }
+}
+
+/*
+* Evaluates data as code, in the given environment or else the current scope
+*
+* (eval '(+ 1 2)) => 3
+* (eval '(define x 1) env) => None
+*/
+pub fn evaldef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_arity_range("eval", &args, 1, 2)?;
+    let (expr, env) = match &args[..] {
+        [expr] => (expr, scope.clone()),
+        [expr, env] => (expr, environment_arg("eval", env, scope)?),
+        _ => unreachable!("arity was checked"),
+    };
+
+    let data = eval_arg(expr, scope)?;
+    Ok(Tail::Eval(code(&data, expr.span())?, env))
+}
+
+/*
+* The scope it is called from, as an environment
+*
+* (define (f x) (current-environment))
+* (eval 'x (f 1)) => 1
+*/
+pub fn currentenvironmentdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("current-environment", &args, 0)?;
+
+    Ok(Some(Result::Environment(scope.clone())))
+}
+
+/*
+* A new environment holding only the builtins, which nothing else shares
+*
+* (eval '(+ 1 2) (base-environment)) => 3
+*/
+pub fn baseenvironmentdef(args: Vec<AstNode>, _scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("base-environment", &args, 0)?;
+
+    Ok(Some(Result::Environment(Scope::base())))
+}
+
+/*
+* A new environment whose lookups fall back to parent. Without a parent it is
+* completely empty, so code evaluated in it can only use what is defined there
+*
+* (define env (make-environment (current-environment)))
+* (eval '(define x 1) env) => None
+*/
+pub fn makeenvironmentdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity_range("make-environment", &args, 0, 1)?;
+    let env = match args.first() {
+        None => Scope::empty(None),
+        Some(parent) => environment_arg("make-environment", parent, scope)?.child(),
+    };
+
+    Ok(Some(Result::Environment(env)))
+}
+
+// Counts continuations made, so each call/cc can tell its own apart
+static CONTINUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/*
+* Calls a function with the current continuation, a function that returns its
+* argument from the call/cc when called. Continuations only escape, so calling
+* one after its call/cc has returned is an error
+*
+* (call/cc (lambda (k) (+ 1 (k 2)))) => 2
+* (call-with-current-continuation (lambda (k) 3)) => 3
+*/
+pub fn callccdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("call/cc", &args, 1)?;
+
+    let f = eval_arg(&args[0], scope)?;
+    let id = CONTINUATIONS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let depth = trace::depth();
+    match exec::apply(f, vec![Result::Continuation(id)], scope) {
+        Err(RiskError::Escape(escaped, value)) if escaped == id => {
+            trace::truncate(depth);
+            Ok(*value)
+        }
+        other => other,
+    }
+}
+
+/*
+* The calls in progress where it's called from, innermost first, each as a
+* list of the function's name and its arguments. Builtins evaluate their own
+* arguments, so their calls show the arguments as written
+*
+* (define (f x) (list (backtrace))) (f 1) => (((list (backtrace)) (f 1)))
+*/
+pub fn backtracedef(args: Vec<AstNode>, _scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("backtrace", &args, 0)?;
+
+    // The first frame is this call to backtrace
+    let frames = trace::frames()
+        .iter()
+        .skip(1)
+        .map(|frame| frame.to_list())
+        .collect();
+
+    Ok(Some(pair::list(frames)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::{eval, shows};
+
+    #[test]
+    fn apply_spreads_a_list_into_a_call() {
+        assert_eq!(shows("(apply + '(1 2 3))"), "6");
+        assert_eq!(shows("(apply list 1 2 '(3 4))"), "(1 2 3 4)");
+        assert_eq!(shows("(apply (lambda (a b) (- a b)) '(5 2))"), "3");
+        assert_eq!(shows("(apply list '())"), "()");
+    }
+
+    #[test]
+    fn apply_passes_keyword_arguments() {
+        let f = "(define (f a #:k 0) (list a k))";
+
+        assert_eq!(shows(&format!("{} (apply f '(1 #:k 2))", f)), "(1 2)");
+        assert_eq!(shows(&format!("{} (apply f #:k 2 '(1))", f)), "(1 2)");
+        assert_eq!(shows(&format!("{} (apply f '(1))", f)), "(1 0)");
+        assert!(matches!(
+            eval(&format!("{} (apply f '(1 #:j 2))", f)),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval(&format!("{} (apply f '(1 #:k))", f)),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval(&format!("{} (apply f '(1 #:k 2 #:k 3))", f)),
+            Err(RiskError::Arity(..))
+        ));
+    }
+
+    #[test]
+    fn apply_calls_in_tail_position() {
+        assert_eq!(
+            shows(
+                "(define (count n) (if (= n 0) 'done (apply count (list (- n 1))))) (count 10000)"
+            ),
+            "done"
+        );
+    }
+
+    #[test]
+    fn apply_rejects_bad_arguments() {
+        assert!(matches!(eval("(apply +)"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(apply + 1 2)"), Err(RiskError::Type(..))));
+        assert!(matches!(
+            eval("(apply (lambda (a) a) '(1 2))"),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval("(apply quote '(a))"),
+            Err(RiskError::Type(..))
+        ));
+        assert!(matches!(
+            eval("(apply if '(#t 1 2))"),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn apply_errors_point_at_the_call() {
+        assert_eq!(
+            eval("(define x 1)\n(apply car '(1))")
+                .unwrap_err()
+                .to_string(),
+            "<repl>:2:1: Type error: <car> expected a pair, received 1"
+        );
+    }
+
+    #[test]
+    fn eval_runs_data_as_code() {
+        assert_eq!(shows("(eval '(+ 1 2))"), "3");
+        assert_eq!(shows("(eval (list '* 2 3))"), "6");
+        assert_eq!(shows("(eval 4)"), "4");
+        assert_eq!(shows("(define x 5) (eval 'x)"), "5");
+        assert_eq!(shows("(eval '(define y 1)) y"), "1");
+    }
+
+    #[test]
+    fn eval_uses_the_given_environment() {
+        assert_eq!(
+            shows("(define (f x) (current-environment)) (define x 2) (eval 'x (f 1))"),
+            "1"
+        );
+        assert_eq!(
+            shows(
+                "(define env (make-environment (current-environment)))
+                 (eval '(define x 1) env)
+                 (list (eval 'x env) (eval '(+ x 1) env))"
+            ),
+            "(1 2)"
+        );
+        assert!(matches!(
+            eval(
+                "(define env (make-environment (current-environment))) (eval '(define x 1) env) x"
+            ),
+            Err(RiskError::Unbound(..))
+        ));
+    }
+
+    #[test]
+    fn environments_can_restrict_what_code_sees() {
+        assert_eq!(shows("(eval '(+ 1 2) (base-environment))"), "3");
+        assert!(matches!(
+            eval("(define secret 1) (eval 'secret (base-environment))"),
+            Err(RiskError::Unbound(..))
+        ));
+        assert!(matches!(
+            eval("(eval '(+ 1 2) (make-environment))"),
+            Err(RiskError::Unbound(..))
+        ));
+        assert_eq!(shows("(eval 7 (make-environment))"), "7");
+        assert_eq!(shows("(eval 1.5 (make-environment))"), "1.5");
+        assert_eq!(shows("(eval #t (make-environment))"), "#t");
+        assert_eq!(shows("(eval \"s\" (make-environment))"), "\"s\"");
+        assert_eq!(shows("(eval '#:k (make-environment))"), "#:k");
+    }
+
+    #[test]
+    fn eval_rejects_bad_arguments() {
+        assert!(matches!(eval("(eval)"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(eval 1 2)"), Err(RiskError::Type(..))));
+        assert!(matches!(eval("(eval car)"), Err(RiskError::Type(..))));
+        assert!(matches!(
+            eval("(make-environment 1)"),
+            Err(RiskError::Type(..))
+        ));
+        assert!(matches!(
+            eval("(current-environment 1)"),
+            Err(RiskError::Arity(..))
+        ));
+    }
+
+    #[test]
+    fn call_cc_returns_normally_without_escaping() {
+        assert_eq!(shows("(call/cc (lambda (k) 3))"), "3");
+        assert_eq!(
+            shows("(call-with-current-continuation (lambda (k) 3))"),
+            "3"
+        );
+    }
+
+    #[test]
+    fn continuations_escape_early() {
+        assert_eq!(shows("(+ 1 (call/cc (lambda (k) (+ 10 (k 2)))))"), "3");
+        assert_eq!(
+            shows(
+                "(define (first-negative xs)
+                   (call/cc (lambda (return)
+                     (let loop ((xs xs))
+                       (cond ((null? xs) #f)
+                             ((negative? (car xs)) (return (car xs)))
+                             (else (loop (cdr xs))))))))
+                 (list (first-negative '(1 -2 3 -4)) (first-negative '(1 2)))"
+            ),
+            "(-2 #f)"
+        );
+        assert_eq!(shows("(call/cc (lambda (k) (k) 1))"), "None");
+        assert_eq!(shows("(call/cc (lambda (k) (apply k '(4))))"), "4");
+    }
+
+    #[test]
+    fn continuations_escape_through_nested_call_cc() {
+        assert_eq!(
+            shows("(call/cc (lambda (outer) (+ 1 (call/cc (lambda (inner) (outer 5))))))"),
+            "5"
+        );
+        assert_eq!(
+            shows("(call/cc (lambda (outer) (+ 1 (call/cc (lambda (inner) (inner 5))))))"),
+            "6"
+        );
+    }
+
+    #[test]
+    fn continuations_only_escape() {
+        assert_eq!(
+            eval("(define saved #f) (call/cc (lambda (k) (set! saved k))) (saved 1)")
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: continuation called after its call/cc returned"
+        );
+        assert!(matches!(
+            eval("(call/cc (lambda (k) (k 1 2)))"),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(eval("(call/cc 1)"), Err(RiskError::Type(..))));
+    }
+
+    #[test]
+    fn backtrace_lists_the_calls_in_progress() {
+        assert_eq!(shows("(backtrace)"), "()");
+        assert_eq!(
+            shows("(define (f x) (list (backtrace))) (define (g) (car (f 1))) (g)"),
+            "((list (backtrace)) (f 1) (car (f 1)) (g))"
+        );
+        assert_eq!(shows("(define (f x) (backtrace)) (f 1) (f 2)"), "((f 2))");
+        assert_eq!(
+            shows("(apply (lambda (x) (backtrace)) '(1))"),
+            "((lambda 1))"
+        );
+    }
+}
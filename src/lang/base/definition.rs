@@ -2,12 +2,18 @@ use std::collections::HashSet;
 
 use crate::lang::{
     ast::AstNode,
+    base::{check_arity, check_min_arity, eval_arg},
     exec::Program,
-    scope::Scope,
-    types::{result::Result, userfunc::FnDef},
+    scope::{Scope, original_name},
+    token::Span,
+    types::{
+        error::{RiskError, RiskResult},
+        result::Result,
+        userfunc::{FnDef, KeywordParam, Params},
+    },
 };
 
-fn duplicate_params(params: Vec<String>) -> bool {
+fn duplicate_params(params: Vec<&str>) -> bool {
     let mut set = HashSet::new();
 
     for param in params.iter() {
@@ -16,7 +22,84 @@ fn duplicate_params(params: Vec<String>) -> bool {
         }
     }
 
-    return false;
+    false
+}
+
+// Reads a function signature into its parameters. Plain symbols are required,
+// (name default) is optional and must come after those, #:name default can be
+// passed by name anywhere in the call and . name collects the rest
+//
+// (a b (c 1) #:d 2 . rest)
+pub fn parse_params(params: &[AstNode], sig_span: &Span) -> RiskResult<Params> {
+    let mut parsed = Params::default();
+
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match param {
+            AstNode::Leaf(dot, span) if dot == "." => match (iter.next(), iter.next()) {
+                (Some(AstNode::Leaf(rest, _)), None) if rest != "." => {
+                    parsed.rest = Some(rest.to_string())
+                }
+                _ => {
+                    return Err(RiskError::Syntax(
+                        ". must be followed by exactly one parameter name".to_string(),
+                        Some(span.clone()),
+                    ));
+                }
+            },
+            AstNode::Leaf(name, span) => {
+                if !parsed.optional.is_empty() {
+                    return Err(RiskError::Syntax(
+                        "Required parameters must come before optional ones".to_string(),
+                        Some(span.clone()),
+                    ));
+                }
+
+                parsed.required.push(name.to_string())
+            }
+            AstNode::AST(parts, _) => match &parts[..] {
+                [AstNode::Leaf(name, _), default] if name != "." => {
+                    parsed.optional.push((name.to_string(), default.clone()))
+                }
+                _ => {
+                    return Err(RiskError::Syntax(
+                        "Optional parameters must be written (name default)".to_string(),
+                        Some(param.span().clone()),
+                    ));
+                }
+            },
+            AstNode::Keyword(name, span) => match iter.next() {
+                Some(default) if !matches!(default, AstNode::Leaf(d, _) if d == ".") => {
+                    parsed.keywords.push(KeywordParam {
+                        keyword: original_name(name).to_string(),
+                        name: name.to_string(),
+                        default: default.clone(),
+                    })
+                }
+                _ => {
+                    return Err(RiskError::Syntax(
+                        format!("Keyword parameter #:{} must have a default", name),
+                        Some(span.clone()),
+                    ));
+                }
+            },
+            AstNode::Str(_, span) => {
+                return Err(RiskError::Syntax(
+                    "All function parameters must be simple strings".to_string(),
+                    Some(span.clone()),
+                ));
+            }
+        }
+    }
+
+    if duplicate_params(parsed.names()) {
+        return Err(RiskError::Syntax(
+            "Function definition cannot have duplicated parameters".to_string(),
+            Some(sig_span.clone()),
+        ));
+    }
+
+    Ok(parsed)
 }
 
 /*
@@ -24,70 +107,172 @@ fn duplicate_params(params: Vec<String>) -> bool {
 *
 * The following forms are supported
 *
-* (define (fn params...) expr) => None
+* (define (fn params...) expr...) => None
+* (define (fn params... (opt default)... . rest) expr...) => None
+* (define (fn params... #:key default...) expr...) => None
 * (define var expr) => None
 */
-pub fn definitiondef(args: Vec<AstNode>, scope: &mut Scope) -> Option<Result> {
+pub fn definitiondef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
     match &args[..] {
-        [] => panic!("Empty call to define"),
-        [_] => panic!("Must provide right hand side to set the left hand side to"),
-        [subject, expr] => match subject {
-            AstNode::Leaf(varname) => {
-                let val = Program::new(expr.to_owned(), scope).exec();
+        [] => Err(RiskError::Arity("Empty call to define".to_string(), None)),
+        [_] => Err(RiskError::Arity(
+            "Must provide right hand side to set the left hand side to".to_string(),
+            None,
+        )),
+        [subject, body @ ..] => match subject {
+            AstNode::Leaf(varname, _) => {
+                let expr = match body {
+                    [expr] => expr,
+                    _ => {
+                        return Err(RiskError::Arity(
+                            "define can only be called with two parameters".to_string(),
+                            None,
+                        ));
+                    }
+                };
+                let val = Program::new(expr.to_owned(), scope).exec()?;
 
                 match val {
                     Some(result) => {
-                        scope.map.insert(varname.to_string(), result);
-                        None
+                        // Functions take the first name they're given
+                        let result = match result {
+                            Result::FnDef(f) if f.name.is_none() => Result::FnDef(f.named(varname)),
+                            result => result,
+                        };
+                        scope.define(varname, result);
+                        Ok(None)
                     }
-                    None => panic!("Right hand side evaluated to nothing"),
+                    None => Err(RiskError::Runtime(
+                        "Right hand side evaluated to nothing".to_string(),
+                        Some(expr.span().clone()),
+                    )),
                 }
             }
-            AstNode::AST(function_signature) => match &function_signature[..] {
-                [] => panic!("Must provide function name"),
+            AstNode::Str(_, span) => Err(RiskError::Syntax(
+                "Cannot define a string literal".to_string(),
+                Some(span.clone()),
+            )),
+            AstNode::Keyword(_, span) => Err(RiskError::Syntax(
+                "Cannot define a keyword".to_string(),
+                Some(span.clone()),
+            )),
+            AstNode::AST(function_signature, sig_span) => match &function_signature[..] {
+                [] => Err(RiskError::Syntax(
+                    "Must provide function name".to_string(),
+                    Some(sig_span.clone()),
+                )),
                 [fname, params @ ..] => match fname {
-                    AstNode::Leaf(n) => {
-                        let str_params = params.iter().map(|p| match p {
-                            AstNode::Leaf(p) => p.to_string(),
-                            AstNode::AST(_) => {
-                                panic!("All function parameters must be simple strings")
-                            }
-                        });
-
-                        if duplicate_params(str_params.clone().collect()) {
-                            panic!("Function definition cannot have duplicated parameters")
-                        }
-
-                        scope.map.insert(
-                            n.to_string(),
-                            Result::FnDef(FnDef::new(str_params.collect(), expr.clone())),
+                    AstNode::Leaf(n, _) => {
+                        let params = parse_params(params, sig_span)?;
+
+                        scope.define(
+                            n,
+                            Result::FnDef(
+                                FnDef::with_params(params, body.to_vec(), scope.clone()).named(n),
+                            ),
                         );
-                        None
+                        Ok(None)
                     }
-                    AstNode::AST(_) => panic!("Cannot use an expression as function name"),
+                    _ => Err(RiskError::Syntax(
+                        "Cannot use an expression as function name".to_string(),
+                        Some(fname.span().clone()),
+                    )),
                 },
             },
         },
-        _ => panic!("define can only be called with two parameters"),
+    }
+}
+
+/*
+* Creates an anonymous function closing over the current scope
+*
+* (lambda (params...) expr...) => FnDef
+*
+* Parameters are written as for define, and a lone symbol takes every argument
+* as a list
+*
+* (lambda args args) => FnDef
+*/
+pub fn lambdadef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_min_arity("lambda", &args, 2)?;
+
+    match &args[..] {
+        [AstNode::AST(params, sig_span), body @ ..] if !body.is_empty() => {
+            Ok(Some(Result::FnDef(FnDef::with_params(
+                parse_params(params, sig_span)?,
+                body.to_vec(),
+                scope.clone(),
+            ))))
+        }
+        [AstNode::Leaf(rest, _), body @ ..] if !body.is_empty() => {
+            let params = Params {
+                rest: Some(rest.to_string()),
+                ..Params::default()
+            };
+
+            Ok(Some(Result::FnDef(FnDef::with_params(
+                params,
+                body.to_vec(),
+                scope.clone(),
+            ))))
+        }
+        [params, ..] => Err(RiskError::Syntax(
+            "lambda parameters must be a list or a single name".to_string(),
+            Some(params.span().clone()),
+        )),
+        [] => unreachable!("arity was checked"),
+    }
+}
+
+/*
+* Updates the nearest enclosing binding of a name, which must already exist
+*
+* (define x 1)
+* (set! x 2) => None
+*/
+pub fn setbangdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("set!", &args, 2)?;
+
+    match &args[..] {
+        [AstNode::Leaf(name, span), expr] => {
+            let value = eval_arg(expr, scope)?;
+
+            if scope.set(name, value) {
+                Ok(None)
+            } else {
+                Err(RiskError::Unbound(name.to_string(), Some(span.clone())))
+            }
+        }
+        [target, ..] => Err(RiskError::Syntax(
+            "set! can only be used on a name".to_string(),
+            Some(target.span().clone()),
+        )),
+        [] => unreachable!("arity was checked"),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lang::types::primitive::Primitive;
-
     use super::*;
+    use crate::lang::{
+        test_util::{eval, shows},
+        types::primitive::Primitive,
+    };
 
     #[test]
-    #[should_panic]
-    fn definitiondef_panics_empty_call() {
-        definitiondef(vec![], &mut Scope::base());
+    fn definitiondef_errors_empty_call() {
+        assert!(matches!(
+            definitiondef(vec![], &mut Scope::base()),
+            Err(RiskError::Arity(..))
+        ));
     }
 
     #[test]
-    #[should_panic]
-    fn definitiondef_panics_missing_rhs() {
-        definitiondef(vec![AstNode::Leaf("x".to_string())], &mut Scope::base());
+    fn definitiondef_errors_missing_rhs() {
+        assert!(matches!(
+            definitiondef(vec![AstNode::leaf("x")], &mut Scope::base()),
+            Err(RiskError::Arity(..))
+        ));
     }
 
     #[test]
@@ -95,20 +280,19 @@ mod tests {
         let s = &mut Scope::base();
         definitiondef(
             vec![
-                AstNode::Leaf("x".to_string()),
-                AstNode::AST(vec![
-                    AstNode::Leaf("if".to_string()),
-                    AstNode::Leaf("#t".to_string()),
-                    AstNode::Leaf("1".to_string()),
+                AstNode::leaf("x"),
+                AstNode::list(vec![
+                    AstNode::leaf("if"),
+                    AstNode::leaf("#t"),
+                    AstNode::leaf("1"),
                 ]),
             ],
             s,
-        );
+        )
+        .unwrap();
 
         assert_eq!(
-            Program::new(AstNode::Leaf("x".to_string()), s)
-                .exec()
-                .unwrap(),
+            Program::new(AstNode::leaf("x"), s).exec().unwrap().unwrap(),
             Result::Primitive(Primitive::I(1))
         )
     }
@@ -118,19 +302,21 @@ mod tests {
         let s = &mut Scope::base();
         definitiondef(
             vec![
-                AstNode::AST(vec![AstNode::Leaf("func".to_string())]),
-                AstNode::AST(vec![
-                    AstNode::Leaf("if".to_string()),
-                    AstNode::Leaf("#t".to_string()),
-                    AstNode::Leaf("1".to_string()),
+                AstNode::list(vec![AstNode::leaf("func")]),
+                AstNode::list(vec![
+                    AstNode::leaf("if"),
+                    AstNode::leaf("#t"),
+                    AstNode::leaf("1"),
                 ]),
             ],
             s,
-        );
+        )
+        .unwrap();
 
         assert_eq!(
-            Program::new(AstNode::AST(vec![AstNode::Leaf("func".to_string())]), s)
+            Program::new(AstNode::list(vec![AstNode::leaf("func")]), s)
                 .exec()
+                .unwrap()
                 .unwrap(),
             Result::Primitive(Primitive::I(1))
         )
@@ -141,53 +327,340 @@ mod tests {
         let s = &mut Scope::base();
         definitiondef(
             vec![
-                AstNode::AST(vec![
-                    AstNode::Leaf("func".to_string()),
-                    AstNode::Leaf("x".to_string()),
-                    AstNode::Leaf("y".to_string()),
+                AstNode::list(vec![
+                    AstNode::leaf("func"),
+                    AstNode::leaf("x"),
+                    AstNode::leaf("y"),
                 ]),
-                AstNode::AST(vec![
-                    AstNode::Leaf("if".to_string()),
-                    AstNode::AST(vec![
-                        AstNode::Leaf("eq?".to_string()),
-                        AstNode::Leaf("x".to_string()),
-                        AstNode::Leaf("y".to_string()),
+                AstNode::list(vec![
+                    AstNode::leaf("if"),
+                    AstNode::list(vec![
+                        AstNode::leaf("eq?"),
+                        AstNode::leaf("x"),
+                        AstNode::leaf("y"),
                     ]),
-                    AstNode::Leaf("1".to_string()),
-                    AstNode::Leaf("2".to_string()),
+                    AstNode::leaf("1"),
+                    AstNode::leaf("2"),
                 ]),
             ],
             s,
-        );
+        )
+        .unwrap();
 
         assert_eq!(
             Program::new(
-                AstNode::AST(vec![
-                    AstNode::Leaf("func".to_string()),
-                    AstNode::Leaf("1".to_string()),
-                    AstNode::Leaf("2".to_string())
+                AstNode::list(vec![
+                    AstNode::leaf("func"),
+                    AstNode::leaf("1"),
+                    AstNode::leaf("2")
                 ]),
                 s
             )
             .exec()
+            .unwrap()
             .unwrap(),
             Result::Primitive(Primitive::I(2))
         )
     }
 
     #[test]
-    #[should_panic]
     fn definitiondef_rejects_when_params_are_dup() {
-        definitiondef(
+        let res = definitiondef(
             vec![
-                AstNode::AST(vec![
-                    AstNode::Leaf("func".to_string()),
-                    AstNode::Leaf("x".to_string()),
-                    AstNode::Leaf("x".to_string()),
+                AstNode::list(vec![
+                    AstNode::leaf("func"),
+                    AstNode::leaf("x"),
+                    AstNode::leaf("x"),
                 ]),
-                AstNode::Leaf("1".to_string()),
+                AstNode::leaf("1"),
             ],
             &mut Scope::base(),
         );
+
+        assert!(matches!(res, Err(RiskError::Syntax(..))));
+    }
+
+    #[test]
+    fn lambdadef_returns_callable_function() {
+        let s = &mut Scope::base();
+        let f = lambdadef(
+            vec![
+                AstNode::list(vec![AstNode::leaf("x")]),
+                AstNode::list(vec![AstNode::leaf("not"), AstNode::leaf("x")]),
+            ],
+            s,
+        )
+        .unwrap()
+        .unwrap();
+        s.define("f", f);
+
+        assert_eq!(
+            Program::new(
+                AstNode::list(vec![AstNode::leaf("f"), AstNode::leaf("#f")]),
+                s
+            )
+            .exec()
+            .unwrap()
+            .unwrap(),
+            Result::Primitive(Primitive::B(true))
+        )
+    }
+
+    #[test]
+    fn lambdadef_rejects_bad_params() {
+        assert!(matches!(
+            lambdadef(
+                vec![
+                    AstNode::Str("x".to_string(), Span::default()),
+                    AstNode::leaf("x")
+                ],
+                &mut Scope::base()
+            ),
+            Err(RiskError::Syntax(..))
+        ));
+        assert!(matches!(
+            lambdadef(
+                vec![
+                    AstNode::list(vec![AstNode::leaf("x"), AstNode::leaf("x")]),
+                    AstNode::leaf("x")
+                ],
+                &mut Scope::base()
+            ),
+            Err(RiskError::Syntax(..))
+        ));
+        assert!(matches!(
+            lambdadef(vec![AstNode::list(vec![])], &mut Scope::base()),
+            Err(RiskError::Arity(..))
+        ));
+    }
+
+    #[test]
+    fn definitiondef_userfunc_body_can_have_many_expressions() {
+        let s = &mut Scope::base();
+        definitiondef(
+            vec![
+                AstNode::list(vec![AstNode::leaf("func"), AstNode::leaf("x")]),
+                AstNode::list(vec![
+                    AstNode::leaf("define"),
+                    AstNode::leaf("y"),
+                    AstNode::leaf("x"),
+                ]),
+                AstNode::list(vec![AstNode::leaf("not"), AstNode::leaf("y")]),
+            ],
+            s,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Program::new(
+                AstNode::list(vec![AstNode::leaf("func"), AstNode::leaf("#f")]),
+                s
+            )
+            .exec()
+            .unwrap()
+            .unwrap(),
+            Result::Primitive(Primitive::B(true))
+        )
+    }
+
+    #[test]
+    fn definitiondef_variable_takes_one_expression() {
+        assert!(matches!(
+            definitiondef(
+                vec![AstNode::leaf("x"), AstNode::leaf("1"), AstNode::leaf("2")],
+                &mut Scope::base()
+            ),
+            Err(RiskError::Arity(..))
+        ));
+    }
+
+    #[test]
+    fn lambdadef_body_can_have_many_expressions() {
+        let s = &mut Scope::base();
+        let f = lambdadef(
+            vec![
+                AstNode::list(vec![]),
+                AstNode::leaf("1"),
+                AstNode::leaf("2"),
+            ],
+            s,
+        )
+        .unwrap()
+        .unwrap();
+        s.define("f", f);
+
+        assert_eq!(
+            Program::new(AstNode::list(vec![AstNode::leaf("f")]), s)
+                .exec()
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::I(2))
+        )
+    }
+
+    #[test]
+    fn set_updates_existing_binding() {
+        assert_eq!(
+            eval("(define x 1) (set! x 2) x").unwrap().unwrap(),
+            Result::Primitive(Primitive::I(2))
+        );
+    }
+
+    #[test]
+    fn set_inside_function_updates_enclosing_binding() {
+        assert_eq!(
+            eval("(define total 0) (define (add! n) (set! total (+ total n))) (add! 2) (add! 3) total")
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::I(5))
+        );
+    }
+
+    #[test]
+    fn set_persists_across_calls_of_a_closure() {
+        assert_eq!(
+            eval(
+                "(define (make-counter) (let ((n 0)) (lambda () (set! n (+ n 1)) n)))
+                 (define a (make-counter))
+                 (define b (make-counter))
+                 (a) (a) (b) (a)"
+            )
+            .unwrap()
+            .unwrap(),
+            Result::Primitive(Primitive::I(3))
+        );
+    }
+
+    #[test]
+    fn set_errors_when_unbound() {
+        match eval("(set! nope 1)") {
+            Err(RiskError::Unbound(name, Some(span))) => {
+                assert_eq!(name, "nope");
+                assert_eq!(span.col, 7);
+            }
+            other => panic!("expected unbound error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_rejects_bad_forms() {
+        assert!(matches!(eval("(set! (x) 1)"), Err(RiskError::Syntax(..))));
+        assert!(matches!(eval("(set! x)"), Err(RiskError::Arity(..))));
+    }
+
+    #[test]
+    fn rest_parameter_collects_remaining_arguments() {
+        assert_eq!(shows("(define (f a . rest) rest) (f 1 2 3)"), "(2 3)");
+        assert_eq!(shows("(define (f a . rest) rest) (f 1)"), "()");
+        assert_eq!(shows("(define (f . all) all) (f 1 2)"), "(1 2)");
+        assert_eq!(shows("((lambda args args) 1 2)"), "(1 2)");
+        assert_eq!(shows("((lambda (a . rest) a) 1 2)"), "1");
+        assert!(matches!(
+            eval("(define (f a . rest) rest) (f)"),
+            Err(RiskError::Arity(..))
+        ));
+    }
+
+    #[test]
+    fn optional_parameters_fall_back_to_defaults() {
+        assert_eq!(shows("(define (f a (b 10)) (+ a b)) (f 1)"), "11");
+        assert_eq!(shows("(define (f a (b 10)) (+ a b)) (f 1 2)"), "3");
+        assert_eq!(shows("(define (f a (b (* a 2))) b) (f 4)"), "8");
+        assert_eq!(
+            shows("(define (f (a 1) . rest) (cons a rest)) (list (f) (f 5 6))"),
+            "((1) (5 6))"
+        );
+        assert!(matches!(
+            eval("(define (f a (b 10)) b) (f 1 2 3)"),
+            Err(RiskError::Arity(..))
+        ));
+    }
+
+    #[test]
+    fn variadic_helpers_can_be_written_in_risk() {
+        assert_eq!(
+            shows(
+                "(define (sum . xs) (if (null? xs) 0 (+ (car xs) (apply sum (cdr xs)))))
+                 (sum 1 2 3 4)"
+            ),
+            "10"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_parameter_lists() {
+        for source in [
+            "(define (f a .) a)",
+            "(define (f . a b) a)",
+            "(define (f (a 1) b) a)",
+            "(define (f (a)) a)",
+            "(define (f a . a) a)",
+            "(lambda (a (a 1)) a)",
+        ] {
+            assert!(
+                matches!(eval(source), Err(RiskError::Syntax(..))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn keyword_parameters_are_passed_by_name() {
+        let connect = "(define (connect host #:timeout 30 #:retries (* timeout 2))
+                         (list host timeout retries))";
+
+        assert_eq!(shows(&format!("{} (connect 'db)", connect)), "(db 30 60)");
+        assert_eq!(
+            shows(&format!(
+                "{} (connect #:retries 1 'db #:timeout 5)",
+                connect
+            )),
+            "(db 5 1)"
+        );
+        assert_eq!(
+            shows(&format!("{} (connect 'db #:timeout 5)", connect)),
+            "(db 5 10)"
+        );
+        assert_eq!(
+            shows("((lambda (#:x 1 . rest) (cons x rest)) 2 #:x 3)"),
+            "(3 2)"
+        );
+    }
+
+    #[test]
+    fn keyword_arguments_are_checked() {
+        let f = "(define (f a #:b 1) b)";
+
+        assert!(matches!(
+            eval(&format!("{} (f 1 #:c 2)", f)),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval(&format!("{} (f 1 #:b 2 #:b 3)", f)),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval(&format!("{} (f 1 #:b)", f)),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval(&format!("{} (f #:b 2)", f)),
+            Err(RiskError::Arity(..))
+        ));
+        assert!(matches!(
+            eval("(define (f #:a) a)"),
+            Err(RiskError::Syntax(..))
+        ));
+        assert!(matches!(
+            eval("(define (f a #:a 1) a)"),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn keywords_evaluate_to_themselves() {
+        assert_eq!(shows("#:x"), "#:x");
+        assert_eq!(shows("'(#:a 1)"), "(#:a 1)");
     }
 }
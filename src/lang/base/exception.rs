@@ -0,0 +1,331 @@
+use crate::lang::{
+    ast::AstNode,
+    base::{check_arity, check_min_arity, eval_arg, eval_args, eval_sequence, logic::cond_clauses},
+    exec::{self, Tail},
+    scope::Scope,
+    trace,
+    types::{
+        condition::Condition,
+        error::{RiskError, RiskResult},
+        pair,
+        primitive::Primitive,
+        result::Result,
+    },
+};
+
+// Evaluates a single argument which has to be an error object
+fn condition_arg(name: &str, arg: &AstNode, scope: &mut Scope) -> RiskResult<Condition> {
+    match eval_arg(arg, scope)? {
+        Result::Condition(c) => Ok(c),
+        value => Err(RiskError::Type(
+            format!("<{}> expected an error object, received {}", name, value),
+            Some(arg.span().clone()),
+        )),
+    }
+}
+
+/*
+* Creates an error object from a message and any number of irritants, the
+* values the error is about
+*
+* (error "not a number:" 'x) => error#not a number: x
+* (raise (error "not a number:" 'x)) => Error: not a number: x
+*/
+pub fn errordef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_min_arity("error", &args, 1)?;
+    let (message, irritants) = args.split_first().expect("arity was checked");
+
+    let message = match eval_arg(message, scope)? {
+        Result::Primitive(Primitive::S(s)) => s,
+        value => value.to_string(),
+    };
+
+    Ok(Some(Result::Condition(Condition {
+        message,
+        irritants: eval_args(irritants, scope)?,
+    })))
+}
+
+/*
+* Raises any value as an exception, unwinding until a guard catches it
+*
+* (raise 'oops) => Uncaught exception: oops
+*/
+pub fn raisedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("raise", &args, 1)?;
+
+    Err(RiskError::Raised(
+        Box::new(eval_arg(&args[0], scope)?),
+        None,
+    ))
+}
+
+/*
+* Evaluates its body, and if anything is raised binds it to name and picks a
+* clause as cond does. When no clause matches the exception carries on
+* unwinding. Failures in builtins are caught as error objects
+*
+* (guard (e ((eq? e 'oops) e) ((error? e) (error-object-message e)))
+*   (raise 'oops)) => oops
+* (guard (e (#t 'caught)) (car 1)) => caught
+*/
+pub fn guarddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_min_arity("guard", &args, 1)?;
+    let (spec, body) = args.split_first().expect("arity was checked");
+
+    let (name, clauses) = match spec {
+        AstNode::AST(parts, _) => match &parts[..] {
+            [AstNode::Leaf(name, _), clauses @ ..] => (name, clauses),
+            _ => {
+                return Err(RiskError::Syntax(
+                    "guard must start with (name clause...)".to_string(),
+                    Some(spec.span().clone()),
+                ));
+            }
+        },
+        _ => {
+            return Err(RiskError::Syntax(
+                "guard must start with (name clause...)".to_string(),
+                Some(spec.span().clone()),
+            ));
+        }
+    };
+
+    let depth = trace::depth();
+    let err = match eval_sequence(body, &mut scope.child()) {
+        Ok(value) => return Ok(Tail::Value(value)),
+        Err(err) => err,
+    };
+
+    match err.condition() {
+        Some(condition) => {
+            // Once caught, the calls it unwound through are over. They're put
+            // back if it carries on unwinding
+            let unwound = trace::split_off(depth);
+
+            let mut handler_scope = scope.child();
+            handler_scope.define(name, condition);
+
+            match cond_clauses(clauses, "guard", &mut handler_scope)? {
+                Some(tail) => Ok(tail),
+                None => {
+                    trace::extend(unwound);
+                    Err(err)
+                }
+            }
+        }
+        None => Err(err),
+    }
+}
+
+/*
+* Calls before, then thunk, then after, returning what thunk did. after is
+* called however thunk is left, whether it returns, raises or escapes through a
+* continuation
+*
+* (dynamic-wind (lambda () 'setup) (lambda () 'body) (lambda () 'cleanup)) => body
+*/
+pub fn dynamicwinddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("dynamic-wind", &args, 3)?;
+
+    let before = eval_arg(&args[0], scope)?;
+    let thunk = eval_arg(&args[1], scope)?;
+    let after = eval_arg(&args[2], scope)?;
+
+    exec::apply(before, vec![], scope)?;
+    let result = exec::apply(thunk, vec![], scope);
+    exec::apply(after, vec![], scope)?;
+
+    result
+}
+
+/*
+* Whether a value is an error object
+*
+* (error? (error "bad")) => #t
+*/
+pub fn errorhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("error?", &args, 1)?;
+
+    let is_error = matches!(eval_arg(&args[0], scope)?, Result::Condition(_));
+
+    Ok(Some(Result::Primitive(Primitive::B(is_error))))
+}
+
+/*
+* The message of an error object
+*
+* (error-object-message (error "bad" 1)) => "bad"
+*/
+pub fn errorobjectmessagedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("error-object-message", &args, 1)?;
+
+    let condition = condition_arg("error-object-message", &args[0], scope)?;
+
+    Ok(Some(Result::Primitive(Primitive::S(condition.message))))
+}
+
+/*
+* The irritants of an error object, as a list
+*
+* (error-object-irritants (error "bad" 1 2)) => (1 2)
+*/
+pub fn errorobjectirritantsdef(
+    args: Vec<AstNode>,
+    scope: &mut Scope,
+) -> RiskResult<Option<Result>> {
+    check_arity("error-object-irritants", &args, 1)?;
+
+    let condition = condition_arg("error-object-irritants", &args[0], scope)?;
+
+    Ok(Some(pair::list(condition.irritants)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::{eval, shows};
+
+    #[test]
+    fn error_objects_hold_a_message_and_irritants() {
+        assert_eq!(shows("(error \"bad value:\" 1 'x)"), "error#bad value: 1 x");
+        assert_eq!(shows("(error-object-message (error \"bad\" 1))"), "\"bad\"");
+        assert_eq!(
+            shows("(error-object-irritants (error \"bad\" 1 2))"),
+            "(1 2)"
+        );
+        assert_eq!(shows("(list (error? (error 'x)) (error? 'x))"), "(#t #f)");
+        assert!(matches!(
+            eval("(error-object-message 1)"),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn raise_unwinds_with_any_value() {
+        assert_eq!(
+            eval("(raise 'oops)").unwrap_err().to_string(),
+            "<repl>:1:1: Uncaught exception: oops"
+        );
+        assert_eq!(
+            eval("(raise (error \"bad value:\" 1))")
+                .unwrap_err()
+                .to_string(),
+            "<repl>:1:1: Error: bad value: 1"
+        );
+    }
+
+    #[test]
+    fn guard_catches_by_predicate() {
+        let classify = "(define (classify thunk)
+                          (guard (e ((eq? e 'oops) (list 'symbol e))
+                                    ((error? e) (error-object-message e))
+                                    ((and (pair? e) e) => car))
+                            (thunk)))";
+
+        assert_eq!(
+            shows(&format!(
+                "{} (classify (lambda () (raise 'oops)))",
+                classify
+            )),
+            "(symbol oops)"
+        );
+        assert_eq!(
+            shows(&format!(
+                "{} (classify (lambda () (raise (error \"bad\"))))",
+                classify
+            )),
+            "\"bad\""
+        );
+        assert_eq!(
+            shows(&format!(
+                "{} (classify (lambda () (raise '(1 2))))",
+                classify
+            )),
+            "1"
+        );
+        assert_eq!(
+            shows(&format!("{} (classify (lambda () 'fine))", classify)),
+            "fine"
+        );
+    }
+
+    #[test]
+    fn guard_reraises_when_no_clause_matches() {
+        assert!(matches!(
+            eval("(guard (e ((eq? e 'oops) e)) (raise 1))"),
+            Err(RiskError::Raised(..))
+        ));
+        assert_eq!(
+            shows(
+                "(guard (outer (#t (list 'outer outer))) (guard (e ((eq? e 'oops) e)) (raise 1)))"
+            ),
+            "(outer 1)"
+        );
+        assert!(matches!(
+            eval("(guard (e ((eq? e 'oops) e)) (car 1))"),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn builtin_failures_are_catchable() {
+        assert_eq!(
+            shows(
+                "(define (f x) x)
+                 (guard (e ((error? e) (error-object-message e))) (f))"
+            ),
+            "\"Arity error: Incorrect number of arguments provided. Expected 1, received 0\""
+        );
+        assert_eq!(shows("(guard (e (#t 'caught)) (car 1))"), "caught");
+        assert_eq!(
+            shows("(guard (e (else 'unbound)) undefined-name)"),
+            "unbound"
+        );
+    }
+
+    #[test]
+    fn guard_does_not_catch_continuations() {
+        assert_eq!(
+            shows("(call/cc (lambda (k) (guard (e (#t 'caught)) (k 'escaped))))"),
+            "escaped"
+        );
+    }
+
+    #[test]
+    fn dynamic_wind_always_runs_after() {
+        let log = "(define log '())
+                   (define (note x) (set! log (cons x log)))
+                   (define (wind thunk)
+                     (dynamic-wind (lambda () (note 'before)) thunk (lambda () (note 'after))))";
+
+        assert_eq!(
+            shows(&format!("{} (list (wind (lambda () 'body)) log)", log)),
+            "(body (after before))"
+        );
+        assert_eq!(
+            shows(&format!(
+                "{} (guard (e (#t (list e log))) (wind (lambda () (raise 'oops))))",
+                log
+            )),
+            "(oops (after before))"
+        );
+        assert_eq!(
+            shows(&format!(
+                "{} (list (call/cc (lambda (k) (wind (lambda () (k 'escaped))))) log)",
+                log
+            )),
+            "(escaped (after before))"
+        );
+    }
+
+    #[test]
+    fn guard_rejects_bad_forms() {
+        assert!(matches!(eval("(guard)"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(guard e 1)"), Err(RiskError::Syntax(..))));
+        assert!(matches!(
+            eval("(guard ((e)) 1)"),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+}
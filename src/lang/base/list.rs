@@ -0,0 +1,179 @@
+use crate::lang::{
+    ast::AstNode,
+    base::{check_arity, eval_arg, eval_args},
+    scope::Scope,
+    types::{
+        error::{RiskError, RiskResult},
+        pair::{self, Pair},
+        primitive::Primitive,
+        result::Result,
+    },
+};
+
+// Evaluates a single argument which has to be a proper list
+fn list_arg(name: &str, arg: &AstNode, scope: &mut Scope) -> RiskResult<Vec<Result>> {
+    let value = eval_arg(arg, scope)?;
+
+    pair::to_vec(&value).ok_or_else(|| {
+        RiskError::Type(
+            format!("<{}> expected a list, received {}", name, value),
+            Some(arg.span().clone()),
+        )
+    })
+}
+
+pub fn consdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("cons", &args, 2)?;
+
+    let car = eval_arg(&args[0], scope)?;
+    let cdr = eval_arg(&args[1], scope)?;
+
+    Ok(Some(Pair::cons(car, cdr)))
+}
+
+pub fn cardef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("car", &args, 1)?;
+
+    match eval_arg(&args[0], scope)? {
+        Result::Pair(p) => Ok(Some(p.car.clone())),
+        value => Err(RiskError::Type(
+            format!("<car> expected a pair, received {}", value),
+            Some(args[0].span().clone()),
+        )),
+    }
+}
+
+pub fn cdrdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("cdr", &args, 1)?;
+
+    match eval_arg(&args[0], scope)? {
+        Result::Pair(p) => Ok(Some(p.cdr.clone())),
+        value => Err(RiskError::Type(
+            format!("<cdr> expected a pair, received {}", value),
+            Some(args[0].span().clone()),
+        )),
+    }
+}
+
+pub fn listdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    Ok(Some(pair::list(eval_args(&args, scope)?)))
+}
+
+pub fn nullhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("null?", &args, 1)?;
+
+    let is_null = eval_arg(&args[0], scope)? == Result::Nil;
+
+    Ok(Some(Result::Primitive(Primitive::B(is_null))))
+}
+
+pub fn pairhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("pair?", &args, 1)?;
+
+    let is_pair = matches!(eval_arg(&args[0], scope)?, Result::Pair(_));
+
+    Ok(Some(Result::Primitive(Primitive::B(is_pair))))
+}
+
+pub fn lengthdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("length", &args, 1)?;
+
+    let len = list_arg("length", &args[0], scope)?.len();
+
+    Ok(Some(Result::Primitive(Primitive::I(len as i32))))
+}
+
+/*
+* Joins lists together. Every argument but the last must be a proper list, the
+* last becomes the tail of the result as is
+*
+* (append) => ()
+* (append (list 1) (list 2 3)) => (1 2 3)
+* (append (list 1) 2) => (1 . 2)
+*/
+pub fn appenddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    match &args[..] {
+        [] => Ok(Some(Result::Nil)),
+        [lists @ .., tail] => {
+            let mut items = Vec::new();
+            for l in lists {
+                items.extend(list_arg("append", l, scope)?);
+            }
+
+            Ok(Some(pair::list_with_tail(items, eval_arg(tail, scope)?)))
+        }
+    }
+}
+
+pub fn reversedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("reverse", &args, 1)?;
+
+    let mut items = list_arg("reverse", &args[0], scope)?;
+    items.reverse();
+
+    Ok(Some(pair::list(items)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::{eval, shows};
+
+    #[test]
+    fn cons_car_cdr() {
+        assert_eq!(shows("(cons 1 2)"), "(1 . 2)");
+        assert_eq!(shows("(cons 1 (cons 2 (list)))"), "(1 2)");
+        assert_eq!(shows("(car (cons 1 2))"), "1");
+        assert_eq!(shows("(cdr (cons 1 2))"), "2");
+        assert_eq!(shows("(cdr (list 1 2 3))"), "(2 3)");
+    }
+
+    #[test]
+    fn car_cdr_reject_non_pairs() {
+        assert!(matches!(eval("(car (list))"), Err(RiskError::Type(..))));
+        assert!(matches!(eval("(cdr 1)"), Err(RiskError::Type(..))));
+        assert!(matches!(eval("(car)"), Err(RiskError::Arity(..))));
+    }
+
+    #[test]
+    fn list_builds_proper_lists() {
+        assert_eq!(shows("(list)"), "()");
+        assert_eq!(shows(r#"(list 1 "two" (list #t))"#), r#"(1 "two" (#t))"#);
+    }
+
+    #[test]
+    fn predicates() {
+        assert_eq!(shows("(null? (list))"), "#t");
+        assert_eq!(shows("(null? (list 1))"), "#f");
+        assert_eq!(shows("(null? 0)"), "#f");
+        assert_eq!(shows("(pair? (cons 1 2))"), "#t");
+        assert_eq!(shows("(pair? (list))"), "#f");
+    }
+
+    #[test]
+    fn length_of_lists() {
+        assert_eq!(shows("(length (list))"), "0");
+        assert_eq!(shows("(length (list 1 2 3))"), "3");
+        assert!(matches!(
+            eval("(length (cons 1 2))"),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn append_lists() {
+        assert_eq!(shows("(append)"), "()");
+        assert_eq!(shows("(append (list 1) (list) (list 2 3))"), "(1 2 3)");
+        assert_eq!(shows("(append (list 1) 2)"), "(1 . 2)");
+        assert!(matches!(
+            eval("(append 1 (list 2))"),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn reverse_lists() {
+        assert_eq!(shows("(reverse (list 1 2 3))"), "(3 2 1)");
+        assert_eq!(shows("(reverse (list))"), "()");
+    }
+}
@@ -1,87 +1,277 @@
 use crate::lang::{
     ast::AstNode,
-    exec::Program,
-    scope::Scope,
-    types::{primitive::Primitive, result::Result},
+    base::{
+        check_arity, check_arity_range, check_min_arity, eval_arg, quote::datum, tail_sequence,
+    },
+    exec::{self, Program, Tail},
+    scope::{Scope, original_name},
+    types::{
+        error::{RiskError, RiskResult},
+        primitive::Primitive,
+        result::Result,
+    },
 };
 
-pub fn ifdef(args: Vec<AstNode>, scope: &mut Scope) -> Option<Result> {
-    if args.len() < 2 || args.len() > 3 {
-        panic!(
-            "Incorrect number of arguments to function <if>, expected 2 or 3, received {}",
-            args.len()
-        );
+// Everything except an explicit #f counts as true
+pub fn truthy(value: &Option<Result>) -> bool {
+    !matches!(value, Some(Result::Primitive(Primitive::B(false))))
+}
+
+pub fn ifdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_arity_range("if", &args, 2, 3)?;
+
+    let gate = Program::new(args[0].clone(), scope).exec()?;
+    let branch = if truthy(&gate) {
+        args.get(1)
+    } else {
+        args.get(2)
+    };
+
+    Ok(match branch {
+        Some(expr) => Tail::Eval(expr.clone(), scope.clone()),
+        None => Tail::Value(None),
+    })
+}
+
+pub fn eqhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("eq?", &args, 2)?;
+
+    let cmp = Program::new(args[0].clone(), scope).exec()?;
+    let to = Program::new(args[1].clone(), scope).exec()?;
+
+    Ok(Some(Result::Primitive(Primitive::B(cmp == to))))
+}
+
+pub fn notdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("not", &args, 1)?;
+
+    let tonot = Program::new(args[0].clone(), scope).exec()?;
+
+    match tonot {
+        Some(Result::Primitive(Primitive::B(b))) => Ok(Some(Result::Primitive(Primitive::B(!b)))),
+        // anything but explicit #f nots to #f
+        _ => Ok(Some(Result::Primitive(Primitive::B(false)))),
     }
+}
+
+/*
+* Evaluates its arguments in order in the current scope, producing the value of
+* the last one
+*
+* (begin (define x 1) (+ x 1)) => 2
+* (begin) => None
+*/
+pub fn begindef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    tail_sequence(&args, scope)
+}
 
-    let gate = Program::new(args[0].clone(), scope).exec();
-    let on_true = |s: &mut Scope| Program::new(args[1].clone(), s).exec();
-    let on_false = |s: &mut Scope| Program::new(args[2].clone(), s).exec();
+/*
+* Evaluates its arguments left to right, stopping at the first false one
+*
+* (and) => #t
+* (and 1 2) => 2
+* (and 1 #f (undefined)) => #f
+*/
+pub fn anddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    let (tail, init) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(Tail::Value(Some(Result::Primitive(Primitive::B(true))))),
+    };
 
-    match gate {
-        Some(s) => match s {
-            Result::Primitive(p) => match p {
-                Primitive::B(b) => {
-                    // Everything expects #f explicitly is true
-                    if !b { on_false(scope) } else { on_true(scope) }
-                }
-                _ => on_true(scope),
-            },
-            _ => on_true(scope),
-        },
-        None => on_true(scope),
+    for arg in init.iter() {
+        let last = Program::new(arg.clone(), scope).exec()?;
+        if !truthy(&last) {
+            return Ok(Tail::Value(last));
+        }
     }
+
+    Ok(Tail::Eval(tail.clone(), scope.clone()))
 }
 
-pub fn eqhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> Option<Result> {
-    if args.len() != 2 {
-        panic!(
-            "Incorrect number of arguments to function <eq?>, expected 2, received {}",
-            args.len()
-        )
+/*
+* Evaluates its arguments left to right, stopping at the first true one
+*
+* (or) => #f
+* (or #f 2 (undefined)) => 2
+*/
+pub fn ordef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    let (tail, init) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(Tail::Value(Some(Result::Primitive(Primitive::B(false))))),
+    };
+
+    for arg in init.iter() {
+        let last = Program::new(arg.clone(), scope).exec()?;
+        if truthy(&last) {
+            return Ok(Tail::Value(last));
+        }
     }
 
-    let cmp = Program::new(args[0].clone(), scope).exec();
-    let to = Program::new(args[1].clone(), scope).exec();
-
-    match cmp {
-        Some(from) => match to {
-            Some(res) => Some(Result::Primitive(Primitive::B(from == res))),
-            None => Some(Result::Primitive(Primitive::B(false))),
-        },
-        None => match to {
-            Some(_) => Some(Result::Primitive(Primitive::B(false))),
-            None => Some(Result::Primitive(Primitive::B(true))),
-        },
+    Ok(Tail::Eval(tail.clone(), scope.clone()))
+}
+
+fn is_symbol(node: &AstNode, name: &str) -> bool {
+    matches!(node, AstNode::Leaf(l, _) if original_name(l) == name)
+}
+
+fn clause(node: &AstNode, form: &str) -> RiskResult<Vec<AstNode>> {
+    match node {
+        AstNode::AST(parts, _) if !parts.is_empty() => Ok(parts.to_vec()),
+        _ => Err(RiskError::Syntax(
+            format!("<{}> clauses must be non-empty lists", form),
+            Some(node.span().clone()),
+        )),
     }
 }
 
-pub fn notdef(args: Vec<AstNode>, scope: &mut Scope) -> Option<Result> {
-    if args.len() != 1 {
-        panic!(
-            "Incorrect number of arguments to function <not>, expected 1, received {}",
-            args.len()
-        )
+/*
+* Evaluates the body of the first clause whose test is true. A clause with no
+* body produces the value of its test, and (test => f) calls f with it
+*
+* (cond (#f 1) ((eq? 1 1) 2) (else 3)) => 2
+* (cond ((car '(5)) => (lambda (x) (+ x 1)))) => 6
+*/
+pub fn conddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    Ok(cond_clauses(&args, "cond", scope)?.unwrap_or(Tail::Value(None)))
+}
+
+// Runs the first cond style clause whose test is true, or returns None when
+// none of them are
+pub fn cond_clauses(
+    clauses: &[AstNode],
+    form: &str,
+    scope: &mut Scope,
+) -> RiskResult<Option<Tail>> {
+    for (i, node) in clauses.iter().enumerate() {
+        let parts = clause(node, form)?;
+        let (test, body) = parts.split_first().expect("clause is non-empty");
+
+        if is_symbol(test, "else") {
+            if i + 1 != clauses.len() {
+                return Err(RiskError::Syntax(
+                    format!("else must be the last clause of <{}>", form),
+                    Some(node.span().clone()),
+                ));
+            }
+
+            return tail_sequence(body, scope).map(Some);
+        }
+
+        let value = Program::new(test.clone(), scope).exec()?;
+        if !truthy(&value) {
+            continue;
+        }
+
+        let tail = match body {
+            [] => Ok(Tail::Value(value)),
+            [arrow, receiver] if is_symbol(arrow, "=>") => {
+                let f = eval_arg(receiver, scope)?;
+                let arg = value.ok_or_else(|| {
+                    RiskError::Runtime(
+                        "Cannot pass none to function".to_string(),
+                        Some(test.span().clone()),
+                    )
+                })?;
+
+                exec::apply_tail(f, vec![arg], scope).map_err(|e| e.with_span(node.span()))
+            }
+            [arrow, ..] if is_symbol(arrow, "=>") => Err(RiskError::Syntax(
+                "=> must be followed by exactly one expression".to_string(),
+                Some(node.span().clone()),
+            )),
+            _ => tail_sequence(body, scope),
+        };
+
+        return tail.map(Some);
     }
 
-    let tonot = Program::new(args[0].clone(), scope).exec();
+    Ok(None)
+}
+
+/*
+* Evaluates the body of the first clause listing a datum equal to the key
+*
+* (case (car '(b)) ((a e i o u) 'vowel) ((w y) 'semivowel) (else 'consonant))
+*   => consonant
+*/
+pub fn casedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    check_min_arity("case", &args, 1)?;
+    let (key, clauses) = args.split_first().expect("arity was checked");
+    let key = Program::new(key.clone(), scope).exec()?;
 
-    match tonot {
-        Some(s) => match s {
-            Result::Primitive(p) => match p {
-                Primitive::B(b) => {
-                    if b {
-                        Some(Result::Primitive(Primitive::B(false)))
-                    } else {
-                        Some(Result::Primitive(Primitive::B(true)))
+    for (i, node) in clauses.iter().enumerate() {
+        let parts = clause(node, "case")?;
+        let (data, body) = parts.split_first().expect("clause is non-empty");
+
+        let matched = match data {
+            AstNode::Leaf(l, _) if original_name(l) == "else" => {
+                if i + 1 != clauses.len() {
+                    return Err(RiskError::Syntax(
+                        "else must be the last clause of <case>".to_string(),
+                        Some(node.span().clone()),
+                    ));
+                }
+                true
+            }
+            AstNode::AST(data, _) => {
+                let mut matched = false;
+                for d in data.iter() {
+                    if key.as_ref() == Some(&datum(d)?) {
+                        matched = true;
+                        break;
                     }
                 }
-                // anything but explicit #f nots to #f
-                _ => Some(Result::Primitive(Primitive::B(false))),
-            },
-            _ => Some(Result::Primitive(Primitive::B(false))),
-        },
-        None => Some(Result::Primitive(Primitive::B(false))),
+                matched
+            }
+            _ => {
+                return Err(RiskError::Syntax(
+                    "<case> clauses must start with a list of data or else".to_string(),
+                    Some(data.span().clone()),
+                ));
+            }
+        };
+
+        if matched {
+            return tail_sequence(body, scope);
+        }
     }
+
+    Ok(Tail::Value(None))
+}
+
+fn guarded_body(
+    name: &str,
+    args: Vec<AstNode>,
+    scope: &mut Scope,
+    run_when: bool,
+) -> RiskResult<Tail> {
+    check_min_arity(name, &args, 1)?;
+    let (test, body) = args.split_first().expect("arity was checked");
+
+    let gate = Program::new(test.clone(), scope).exec()?;
+    if truthy(&gate) == run_when {
+        tail_sequence(body, scope)
+    } else {
+        Ok(Tail::Value(None))
+    }
+}
+
+/*
+* Evaluates the body in order only when the test is true
+*
+* (when (> 2 1) 'a 'b) => b
+*/
+pub fn whendef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    guarded_body("when", args, scope, true)
+}
+
+/*
+* Evaluates the body in order only when the test is false
+*
+* (unless (> 2 1) 'a) => None
+*/
+pub fn unlessdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+    guarded_body("unless", args, scope, false)
 }
 
 #[cfg(test)]
@@ -93,12 +283,11 @@ mod tests {
         fn if_happy_path() {
             assert_eq!(
                 ifdef(
-                    vec![
-                        AstNode::Leaf("#t".to_string()),
-                        AstNode::Leaf("1".to_string()),
-                    ],
+                    vec![AstNode::leaf("#t"), AstNode::leaf("1"),],
                     &mut Scope::base()
                 )
+                .and_then(exec::resolve)
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::I(1))
             )
@@ -108,13 +297,11 @@ mod tests {
         fn if_with_else() {
             assert_eq!(
                 ifdef(
-                    vec![
-                        AstNode::Leaf("#t".to_string()),
-                        AstNode::Leaf("1".to_string()),
-                        AstNode::Leaf("2".to_string())
-                    ],
+                    vec![AstNode::leaf("#t"), AstNode::leaf("1"), AstNode::leaf("2")],
                     &mut Scope::base()
                 )
+                .and_then(exec::resolve)
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::I(1))
             )
@@ -124,13 +311,11 @@ mod tests {
         fn if_returns_else_when_strictly_false() {
             assert_eq!(
                 ifdef(
-                    vec![
-                        AstNode::Leaf("#f".to_string()),
-                        AstNode::Leaf("1".to_string()),
-                        AstNode::Leaf("2".to_string())
-                    ],
+                    vec![AstNode::leaf("#f"), AstNode::leaf("1"), AstNode::leaf("2")],
                     &mut Scope::base()
                 )
+                .and_then(exec::resolve)
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::I(2))
             )
@@ -140,17 +325,36 @@ mod tests {
         fn if_anything_but_false_is_true() {
             assert_eq!(
                 ifdef(
-                    vec![
-                        AstNode::Leaf("88".to_string()),
-                        AstNode::Leaf("1".to_string()),
-                        AstNode::Leaf("2".to_string())
-                    ],
+                    vec![AstNode::leaf("88"), AstNode::leaf("1"), AstNode::leaf("2")],
                     &mut Scope::base()
                 )
+                .and_then(exec::resolve)
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::I(1))
             );
         }
+
+        #[test]
+        fn if_without_else_is_none_when_false() {
+            assert_eq!(
+                ifdef(
+                    vec![AstNode::leaf("#f"), AstNode::leaf("1"),],
+                    &mut Scope::base()
+                )
+                .and_then(exec::resolve)
+                .unwrap(),
+                None
+            );
+        }
+
+        #[test]
+        fn if_errors_on_bad_arity() {
+            assert!(matches!(
+                ifdef(vec![AstNode::leaf("#t")], &mut Scope::base()),
+                Err(RiskError::Arity(..))
+            ));
+        }
     }
 
     mod eqhuhdef {
@@ -160,24 +364,20 @@ mod tests {
         fn eqhuh_bool_equality() {
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("#t".to_string()),
-                        AstNode::Leaf("#t".to_string())
-                    ],
+                    vec![AstNode::leaf("#t"), AstNode::leaf("#t")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(true))
             );
 
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("#f".to_string()),
-                        AstNode::Leaf("#f".to_string())
-                    ],
+                    vec![AstNode::leaf("#f"), AstNode::leaf("#f")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(true))
             );
@@ -187,12 +387,10 @@ mod tests {
         fn eqhuh_bool_inequality() {
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("#f".to_string()),
-                        AstNode::Leaf("#t".to_string())
-                    ],
+                    vec![AstNode::leaf("#f"), AstNode::leaf("#t")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(false))
             );
@@ -202,12 +400,10 @@ mod tests {
         fn eqhuh_int_equlaity() {
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("100".to_string()),
-                        AstNode::Leaf("100".to_string())
-                    ],
+                    vec![AstNode::leaf("100"), AstNode::leaf("100")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(true))
             );
@@ -217,12 +413,10 @@ mod tests {
         fn eqhuh_int_inequlaity() {
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("100".to_string()),
-                        AstNode::Leaf("200".to_string())
-                    ],
+                    vec![AstNode::leaf("100"), AstNode::leaf("200")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(false))
             );
@@ -232,12 +426,10 @@ mod tests {
         fn eqhuh_float_equality() {
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("100.1".to_string()),
-                        AstNode::Leaf("100.1".to_string())
-                    ],
+                    vec![AstNode::leaf("100.1"), AstNode::leaf("100.1")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(true))
             );
@@ -247,12 +439,10 @@ mod tests {
         fn eqhuh_float_inequality() {
             assert_eq!(
                 eqhuhdef(
-                    vec![
-                        AstNode::Leaf("100.1".to_string()),
-                        AstNode::Leaf("200.2".to_string())
-                    ],
+                    vec![AstNode::leaf("100.1"), AstNode::leaf("200.2")],
                     &mut Scope::base()
                 )
+                .unwrap()
                 .unwrap(),
                 Result::Primitive(Primitive::B(false))
             );
@@ -265,7 +455,9 @@ mod tests {
         #[test]
         fn notdef_true_for_false() {
             assert_eq!(
-                notdef(vec![AstNode::Leaf("#f".to_string())], &mut Scope::base()).unwrap(),
+                notdef(vec![AstNode::leaf("#f")], &mut Scope::base())
+                    .unwrap()
+                    .unwrap(),
                 Result::Primitive(Primitive::B(true))
             )
         }
@@ -273,13 +465,99 @@ mod tests {
         #[test]
         fn notdef_false_for_everything_but_explicit_false() {
             assert_eq!(
-                notdef(vec![AstNode::Leaf("#t".to_string())], &mut Scope::base()).unwrap(),
+                notdef(vec![AstNode::leaf("#t")], &mut Scope::base())
+                    .unwrap()
+                    .unwrap(),
                 Result::Primitive(Primitive::B(false))
             );
             assert_eq!(
-                notdef(vec![AstNode::Leaf("123".to_string())], &mut Scope::base()).unwrap(),
+                notdef(vec![AstNode::leaf("123")], &mut Scope::base())
+                    .unwrap()
+                    .unwrap(),
                 Result::Primitive(Primitive::B(false))
             );
         }
     }
+
+    mod control {
+        use super::super::*;
+        use crate::lang::test_util::{eval, shows};
+
+        #[test]
+        fn begin_sequences_in_current_scope() {
+            assert_eq!(shows("(begin (define x 1) (+ x 1))"), "2");
+            assert_eq!(shows("(begin (define x 1)) x"), "1");
+            assert_eq!(shows("(begin)"), "None");
+        }
+
+        #[test]
+        fn and_short_circuits() {
+            assert_eq!(shows("(and)"), "#t");
+            assert_eq!(shows("(and 1 2)"), "2");
+            assert_eq!(shows("(and 1 #f (undefined))"), "#f");
+            assert!(eval("(and 1 (undefined))").is_err());
+        }
+
+        #[test]
+        fn or_short_circuits() {
+            assert_eq!(shows("(or)"), "#f");
+            assert_eq!(shows("(or #f 2 (undefined))"), "2");
+            assert_eq!(shows("(or #f #f)"), "#f");
+        }
+
+        #[test]
+        fn cond_picks_first_true_clause() {
+            assert_eq!(shows("(cond (#f 1) ((eq? 1 1) 2) (else 3))"), "2");
+            assert_eq!(shows("(cond (#f 1) (else 3))"), "3");
+            assert_eq!(shows("(cond (#f 1))"), "None");
+            assert_eq!(shows("(cond ((car '(5))))"), "5");
+            assert_eq!(shows("(cond (#t 1 2 3))"), "3");
+        }
+
+        #[test]
+        fn cond_arrow_calls_receiver() {
+            assert_eq!(
+                shows("(cond (#f 1) ((car '(5)) => (lambda (x) (+ x 1))))"),
+                "6"
+            );
+            assert_eq!(shows("(cond ((list 1 2) => car))"), "1");
+        }
+
+        #[test]
+        fn cond_rejects_malformed_clauses() {
+            assert!(matches!(
+                eval("(cond (else 1) (#t 2))"),
+                Err(RiskError::Syntax(..))
+            ));
+            assert!(matches!(eval("(cond ())"), Err(RiskError::Syntax(..))));
+            assert!(matches!(eval("(cond 1)"), Err(RiskError::Syntax(..))));
+            assert!(matches!(
+                eval("(cond (1 => quote))"),
+                Err(RiskError::Type(..))
+            ));
+        }
+
+        #[test]
+        fn case_matches_data() {
+            assert_eq!(
+                shows("(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) 'composite))"),
+                "composite"
+            );
+            assert_eq!(
+                shows("(case (car '(b)) ((a e i o u) 'vowel) (else 'consonant))"),
+                "consonant"
+            );
+            assert_eq!(shows("(case 10 ((1) 'one))"), "None");
+            assert_eq!(shows(r#"(case "x" (("x") 1 2))"#), "2");
+        }
+
+        #[test]
+        fn when_and_unless() {
+            assert_eq!(shows("(when (> 2 1) 'a 'b)"), "b");
+            assert_eq!(shows("(when #f (undefined))"), "None");
+            assert_eq!(shows("(unless #f 'a)"), "a");
+            assert_eq!(shows("(unless 1 (undefined))"), "None");
+            assert!(matches!(eval("(when)"), Err(RiskError::Arity(..))));
+        }
+    }
 }
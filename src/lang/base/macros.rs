@@ -0,0 +1,788 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::lang::{
+    ast::AstNode,
+    base::{
+        check_arity, check_min_arity,
+        definition::parse_params,
+        eval_arg,
+        quote::{code, datum},
+    },
+    exec::Nested,
+    scope::{self, Scope},
+    token::Span,
+    trace::{self, Frame},
+    types::{
+        error::{RiskError, RiskResult},
+        result::Result,
+        syntax::{Macro, Rules},
+        userfunc::FnDef,
+    },
+};
+
+// Produces the code to evaluate in place of a call to a macro at span
+pub fn expand(m: &Macro, args: &[AstNode], span: &Span) -> RiskResult<AstNode> {
+    match m {
+        Macro::Function(f) => {
+            let data = args.iter().map(datum).collect::<RiskResult<Vec<_>>>()?;
+
+            // The transformer shows up in backtraces like any other call,
+            // under the macro's name
+            let depth = trace::depth();
+            trace::push(Frame::function(f, Some(span), &data, &[]));
+            let expansion = f.call(data)?;
+            trace::truncate(depth);
+
+            match expansion {
+                Some(expansion) => code(&expansion, span),
+                None => Err(RiskError::Runtime(
+                    "Macro expanded to nothing".to_string(),
+                    Some(span.clone()),
+                )),
+            }
+        }
+        Macro::Rules(rules) => expand_rules(rules, args, span),
+    }
+}
+
+// Expands node once if it is a call to a macro bound in scope
+fn expand_once(node: &AstNode, scope: &Scope) -> RiskResult<Option<AstNode>> {
+    match node {
+        AstNode::AST(nodes, span) => match &nodes[..] {
+            [AstNode::Leaf(head, _), args @ ..] => match scope.get(head) {
+                Some(Result::Macro(m)) => Ok(Some(expand(&m, args, span)?)),
+                _ => Ok(None),
+            },
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/*
+* Defines a macro, a function that is passed the code of its arguments as data
+* and returns the code to evaluate in place of the call. Parameters are written
+* as for define
+*
+* (defmacro (my-unless test . body) `(if ,test #f (begin ,@body))) => None
+* (defmacro my-unless (test . body) `(if ,test #f (begin ,@body))) => None
+*/
+pub fn defmacrodef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_min_arity("defmacro", &args, 2)?;
+
+    let (name, params, sig_span, body) = match &args[..] {
+        [AstNode::AST(sig, sig_span), body @ ..] => match &sig[..] {
+            [AstNode::Leaf(name, _), params @ ..] => (name, params, sig_span, body),
+            _ => {
+                return Err(RiskError::Syntax(
+                    "Must provide macro name".to_string(),
+                    Some(sig_span.clone()),
+                ));
+            }
+        },
+        [
+            AstNode::Leaf(name, _),
+            AstNode::AST(params, sig_span),
+            body @ ..,
+        ] if !body.is_empty() => (name, &params[..], sig_span, body),
+        _ => {
+            return Err(RiskError::Syntax(
+                "defmacro must be written (defmacro (name params...) expr...)".to_string(),
+                Some(args[0].span().clone()),
+            ));
+        }
+    };
+
+    let m = FnDef::with_params(
+        parse_params(params, sig_span)?,
+        body.to_vec(),
+        scope.clone(),
+    )
+    .named(name);
+    scope.define(name, Result::Macro(Macro::Function(m)));
+
+    Ok(None)
+}
+
+/*
+* Expands a quoted form once if it is a macro call, otherwise returns it as is
+*
+* (macroexpand-1 '(my-unless x 1)) => (if x #f (begin 1))
+*/
+pub fn macroexpand1def(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("macroexpand-1", &args, 1)?;
+
+    let form = eval_arg(&args[0], scope)?;
+    match expand_once(&code(&form, args[0].span())?, scope)? {
+        Some(expansion) => Ok(Some(datum(&expansion)?)),
+        None => Ok(Some(form)),
+    }
+}
+
+/*
+* Expands a quoted form until it is no longer a macro call. Forms inside the
+* result are left alone
+*
+* (macroexpand '(my-unless x 1)) => (if x #f (begin 1))
+*/
+pub fn macroexpanddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("macroexpand", &args, 1)?;
+
+    let form = eval_arg(&args[0], scope)?;
+    let mut node = code(&form, args[0].span())?;
+    while let Some(expansion) = expand_once(&node, scope)? {
+        node = expansion;
+    }
+
+    Ok(Some(datum(&node)?))
+}
+
+const ELLIPSIS: &str = "...";
+
+// Counts expansions of syntax-rules macros, so each one renames the symbols it
+// introduces differently
+static EXPANSIONS: AtomicUsize = AtomicUsize::new(0);
+
+// What a pattern variable matched. Variables followed by an ellipsis match once
+// per repetition
+#[derive(Debug, Clone)]
+enum Binding {
+    One(AstNode),
+    Many(Vec<Binding>),
+}
+
+fn is_leaf(node: &AstNode, name: &str) -> bool {
+    matches!(node, AstNode::Leaf(l, _) if l == name)
+}
+
+// Numbers, ellipses and the . of a dotted list are leaves that aren't symbols
+fn is_symbol(name: &str) -> bool {
+    name != "." && name != ELLIPSIS && name.parse::<f64>().is_err()
+}
+
+// Names of the pattern variables in pattern
+fn pattern_vars(pattern: &AstNode, literals: &[String], vars: &mut Vec<String>) -> RiskResult<()> {
+    let _nested = Nested::enter(pattern.span())?;
+
+    match pattern {
+        AstNode::Leaf(l, _) if is_symbol(l) && l != "_" && !literals.contains(l) => {
+            vars.push(l.to_string())
+        }
+        AstNode::AST(nodes, _) => {
+            for node in nodes.iter() {
+                pattern_vars(node, literals, vars)?;
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn match_pattern(
+    pattern: &AstNode,
+    form: &AstNode,
+    literals: &[String],
+    bindings: &mut HashMap<String, Binding>,
+) -> RiskResult<bool> {
+    let _nested = Nested::enter(form.span())?;
+
+    Ok(match pattern {
+        AstNode::Leaf(l, _) if l == "_" => true,
+        AstNode::Leaf(l, _) if literals.contains(l) => {
+            matches!(form, AstNode::Leaf(f, _) if scope::original_name(f) == l)
+        }
+        AstNode::Leaf(l, _) if is_symbol(l) => {
+            bindings.insert(l.to_string(), Binding::One(form.clone()));
+            true
+        }
+        AstNode::Keyword(k, _) => {
+            matches!(form, AstNode::Keyword(f, _) if scope::original_name(f) == k)
+        }
+        AstNode::AST(patterns, _) => match form {
+            AstNode::AST(items, span) => match &patterns[..] {
+                [init @ .., dot, tail] if is_leaf(dot, ".") => {
+                    // The tail matches whatever the items before it leave over,
+                    // which is nothing when they include an ellipsis
+                    let split = match init.iter().any(|p| is_leaf(p, ELLIPSIS)) {
+                        true => items.len(),
+                        false => init.len(),
+                    };
+                    if split > items.len() {
+                        return Ok(false);
+                    }
+                    let rest = AstNode::AST(items[split..].into(), span.clone());
+
+                    match_items(init, &items[..split], literals, bindings)?
+                        && match_pattern(tail, &rest, literals, bindings)?
+                }
+                _ => match_items(patterns, items, literals, bindings)?,
+            },
+            _ => false,
+        },
+        _ => pattern == form,
+    })
+}
+
+// Matches the items of a list against patterns, where a pattern followed by an
+// ellipsis matches as many items as are left over once the others have theirs
+fn match_items(
+    patterns: &[AstNode],
+    items: &[AstNode],
+    literals: &[String],
+    bindings: &mut HashMap<String, Binding>,
+) -> RiskResult<bool> {
+    let (before, repeated, after) = match patterns.iter().position(|p| is_leaf(p, ELLIPSIS)) {
+        Some(i) if i > 0 => (&patterns[..i - 1], &patterns[i - 1], &patterns[i + 1..]),
+        Some(_) => return Ok(false),
+        None => {
+            if patterns.len() != items.len() {
+                return Ok(false);
+            }
+            for (p, item) in patterns.iter().zip(items) {
+                if !match_pattern(p, item, literals, bindings)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+    };
+
+    if items.len() < before.len() + after.len() {
+        return Ok(false);
+    }
+    let middle = &items[before.len()..items.len() - after.len()];
+
+    let mut vars = Vec::new();
+    pattern_vars(repeated, literals, &mut vars)?;
+    let mut matched: Vec<Vec<Binding>> = vec![Vec::new(); vars.len()];
+    for item in middle {
+        let mut repetition = HashMap::new();
+        if !match_pattern(repeated, item, literals, &mut repetition)? {
+            return Ok(false);
+        }
+
+        for (var, bound) in vars.iter().zip(matched.iter_mut()) {
+            bound.push(repetition.remove(var).expect("pattern variable was bound"));
+        }
+    }
+    for (var, bound) in vars.into_iter().zip(matched) {
+        bindings.insert(var, Binding::Many(bound));
+    }
+
+    Ok(
+        match_items(before, &items[..before.len()], literals, bindings)?
+            && match_items(
+                after,
+                &items[items.len() - after.len()..],
+                literals,
+                bindings,
+            )?,
+    )
+}
+
+// Names bound to repetitions that template refers to
+fn repeated_vars(
+    template: &AstNode,
+    bindings: &HashMap<String, Binding>,
+    vars: &mut Vec<String>,
+) -> RiskResult<()> {
+    let _nested = Nested::enter(template.span())?;
+
+    match template {
+        AstNode::Leaf(l, _)
+            if matches!(bindings.get(l), Some(Binding::Many(_))) && !vars.contains(l) =>
+        {
+            vars.push(l.to_string())
+        }
+        AstNode::AST(nodes, _) => {
+            for node in nodes.iter() {
+                repeated_vars(node, bindings, vars)?;
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+// Fills in a template, renaming any symbol that isn't a pattern variable and
+// every keyword
+fn instantiate(
+    template: &AstNode,
+    bindings: &HashMap<String, Binding>,
+    expansion: usize,
+    env: &Scope,
+) -> RiskResult<AstNode> {
+    let _nested = Nested::enter(template.span())?;
+
+    match template {
+        AstNode::Leaf(l, span) => match bindings.get(l) {
+            Some(Binding::One(node)) => Ok(node.clone()),
+            Some(Binding::Many(_)) => Err(RiskError::Syntax(
+                format!("{} must be followed by ... in the template", l),
+                Some(span.clone()),
+            )),
+            None if is_symbol(l) => Ok(AstNode::Leaf(
+                scope::rename(l, expansion, env),
+                span.clone(),
+            )),
+            None => Ok(template.clone()),
+        },
+        // Keywords name the variable a keyword parameter binds, so they're
+        // renamed along with the symbols that refer to it
+        AstNode::Keyword(k, span) => Ok(AstNode::Keyword(
+            scope::rename(k, expansion, env),
+            span.clone(),
+        )),
+        AstNode::AST(nodes, span) => {
+            // (... template) stands for template with its ellipses kept as is
+            if let [escape, escaped] = &nodes[..]
+                && is_leaf(escape, ELLIPSIS)
+            {
+                return Ok(escaped.clone());
+            }
+
+            let mut built = Vec::new();
+            let mut i = 0;
+            while i < nodes.len() {
+                let node = &nodes[i];
+                if !nodes.get(i + 1).is_some_and(|n| is_leaf(n, ELLIPSIS)) {
+                    built.push(instantiate(node, bindings, expansion, env)?);
+                    i += 1;
+                    continue;
+                }
+
+                let mut vars = Vec::new();
+                repeated_vars(node, bindings, &mut vars)?;
+                let repetitions = vars
+                    .iter()
+                    .map(|var| match &bindings[var] {
+                        Binding::Many(bound) => bound.clone(),
+                        Binding::One(_) => unreachable!("repeated_vars only finds Many"),
+                    })
+                    .collect::<Vec<_>>();
+
+                let count = match repetitions.first() {
+                    Some(first) => first.len(),
+                    None => {
+                        return Err(RiskError::Syntax(
+                            "... must follow a template containing a pattern variable".to_string(),
+                            Some(node.span().clone()),
+                        ));
+                    }
+                };
+                if repetitions.iter().any(|r| r.len() != count) {
+                    return Err(RiskError::Syntax(
+                        "Pattern variables under ... matched different numbers of times"
+                            .to_string(),
+                        Some(node.span().clone()),
+                    ));
+                }
+
+                for n in 0..count {
+                    let mut repetition = bindings.clone();
+                    for (var, bound) in vars.iter().zip(repetitions.iter()) {
+                        repetition.insert(var.to_string(), bound[n].clone());
+                    }
+                    built.push(instantiate(node, &repetition, expansion, env)?);
+                }
+                i += 2;
+            }
+
+            // A dotted template whose tail became a list is just a longer list
+            if let [.., dot, AstNode::AST(tail, _)] = &built[..]
+                && is_leaf(dot, ".")
+            {
+                let tail = tail.clone();
+                built.truncate(built.len() - 2);
+                built.extend(tail.iter().cloned());
+            }
+
+            Ok(AstNode::AST(built.into(), span.clone()))
+        }
+        _ => Ok(template.clone()),
+    }
+}
+
+fn expand_rules(rules: &Rules, args: &[AstNode], span: &Span) -> RiskResult<AstNode> {
+    for (pattern, template) in rules.rules.iter() {
+        // The head of a pattern is ignored, so give the use the same one
+        let head = match pattern {
+            AstNode::AST(patterns, _) => patterns[0].clone(),
+            _ => unreachable!("syntax-rules checks patterns are lists"),
+        };
+        let form = AstNode::AST([vec![head], args.to_vec()].concat().into(), span.clone());
+
+        let mut bindings = HashMap::new();
+        if match_pattern(pattern, &form, &rules.literals, &mut bindings)? {
+            let expansion = EXPANSIONS.fetch_add(1, Ordering::Relaxed) + 1;
+            return instantiate(template, &bindings, expansion, &rules.env);
+        }
+    }
+
+    Err(RiskError::Syntax(
+        "No syntax-rules pattern matches this use of the macro".to_string(),
+        Some(span.clone()),
+    ))
+}
+
+/*
+* Creates a macro from pattern and template pairs. A use of the macro is
+* matched against each pattern in turn, ignoring the macro name at its head,
+* and replaced with the template of the first that matches. Symbols listed as
+* literals only match themselves, _ matches anything and a pattern followed
+* by ... matches any number of items
+*
+* Symbols a template introduces are renamed, so bindings made by the expansion
+* never capture variables from the code using the macro, and those it doesn't
+* bind refer to what they do where the macro was defined
+*
+* (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp))))
+*/
+pub fn syntaxrulesdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_min_arity("syntax-rules", &args, 1)?;
+    let (literals, rules) = match args.split_first().expect("arity was checked") {
+        (AstNode::AST(literals, _), rules) => (literals, rules),
+        (other, _) => {
+            return Err(RiskError::Syntax(
+                "syntax-rules literals must be a list".to_string(),
+                Some(other.span().clone()),
+            ));
+        }
+    };
+
+    let literals = literals
+        .iter()
+        .map(|l| match l {
+            AstNode::Leaf(name, _) => Ok(name.to_string()),
+            _ => Err(RiskError::Syntax(
+                "syntax-rules literals must be symbols".to_string(),
+                Some(l.span().clone()),
+            )),
+        })
+        .collect::<RiskResult<Vec<_>>>()?;
+
+    let rules = rules
+        .iter()
+        .map(|rule| match rule {
+            AstNode::AST(parts, _) => match &parts[..] {
+                [pattern @ AstNode::AST(p, _), template] if !p.is_empty() => {
+                    Ok((pattern.clone(), template.clone()))
+                }
+                _ => Err(RiskError::Syntax(
+                    "syntax-rules rules must be written (pattern template)".to_string(),
+                    Some(rule.span().clone()),
+                )),
+            },
+            _ => Err(RiskError::Syntax(
+                "syntax-rules rules must be written (pattern template)".to_string(),
+                Some(rule.span().clone()),
+            )),
+        })
+        .collect::<RiskResult<Vec<_>>>()?;
+
+    Ok(Some(Result::Macro(Macro::Rules(Rules {
+        literals,
+        rules,
+        env: scope.clone(),
+    }))))
+}
+
+/*
+* Binds a name to a macro, usually one made by syntax-rules
+*
+* (define-syntax swap! (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+*   => None
+*/
+pub fn definesyntaxdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("define-syntax", &args, 2)?;
+
+    let name = match &args[0] {
+        AstNode::Leaf(name, _) => name,
+        other => {
+            return Err(RiskError::Syntax(
+                "define-syntax can only be used on a name".to_string(),
+                Some(other.span().clone()),
+            ));
+        }
+    };
+
+    match eval_arg(&args[1], scope)? {
+        m @ Result::Macro(_) => {
+            scope.define(name, m);
+            Ok(None)
+        }
+        value => Err(RiskError::Type(
+            format!("<define-syntax> expected a macro, received {}", value),
+            Some(args[1].span().clone()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::{eval, shows};
+
+    const MY_UNLESS: &str = "(defmacro (my-unless test . body) `(if ,test #f (begin ,@body)))";
+
+    #[test]
+    fn macros_receive_unevaluated_code() {
+        assert_eq!(shows(&format!("{} (my-unless #f 1 2)", MY_UNLESS)), "2");
+        assert_eq!(
+            shows(&format!("{} (my-unless #t (undefined))", MY_UNLESS)),
+            "#f"
+        );
+        assert_eq!(shows("(defmacro (quoted x) `',x) (quoted (a b))"), "(a b)");
+    }
+
+    #[test]
+    fn macros_can_be_written_with_the_name_first() {
+        assert_eq!(
+            shows(
+                "(defmacro swap! (a b) `(let ((tmp ,a)) (set! ,a ,b) (set! ,b tmp)))
+                 (define x 1) (define y 2) (swap! x y) (list x y)"
+            ),
+            "(2 1)"
+        );
+    }
+
+    #[test]
+    fn macros_can_expand_into_themselves() {
+        assert_eq!(
+            shows(
+                "(defmacro (my-or . xs)
+                   (if (null? xs) #f `(let ((v ,(car xs))) (if v v (my-or ,@(cdr xs))))))
+                 (my-or #f #f 3)"
+            ),
+            "3"
+        );
+    }
+
+    #[test]
+    fn macro_expansions_run_in_tail_position() {
+        assert_eq!(
+            shows(&format!(
+                "{} (define (count n) (my-unless (= n 0) (count (- n 1)))) (count 10000)",
+                MY_UNLESS
+            )),
+            "#f"
+        );
+    }
+
+    #[test]
+    fn macroexpand_shows_expansions() {
+        let defs = format!(
+            "{} (defmacro (my-when test . body) `(my-unless (not ,test) ,@body))",
+            MY_UNLESS
+        );
+
+        assert_eq!(
+            shows(&format!("{} (macroexpand-1 '(my-when x 1))", defs)),
+            "(my-unless (not x) 1)"
+        );
+        assert_eq!(
+            shows(&format!("{} (macroexpand '(my-when x 1))", defs)),
+            "(if (not x) #f (begin 1))"
+        );
+        assert_eq!(shows(&format!("{} (macroexpand '(f x))", defs)), "(f x)");
+        assert_eq!(shows("(macroexpand-1 'x)"), "x");
+    }
+
+    #[test]
+    fn macro_errors() {
+        assert!(matches!(eval("(defmacro (m))"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(defmacro 1 2)"), Err(RiskError::Syntax(..))));
+        assert!(matches!(
+            eval("(defmacro (m) car) (m)"),
+            Err(RiskError::Type(..))
+        ));
+        assert!(matches!(
+            eval("(defmacro (m) (define x 1)) (m)"),
+            Err(RiskError::Runtime(..))
+        ));
+        assert!(matches!(
+            eval("(defmacro (m x) x) (apply m '(1))"),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn macro_transformers_appear_in_backtraces_by_name() {
+        assert!(eval("(defmacro (m x) (car x))\n(m 1)").is_err());
+
+        assert_eq!(
+            trace::take()
+                .iter()
+                .map(|frame| frame.to_string())
+                .collect::<Vec<_>>(),
+            vec!["(car x) at <repl>:1:17", "(m 1) at <repl>:2:1"]
+        );
+    }
+
+    const SWAP: &str = "(define-syntax swap!
+                          (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))";
+
+    const MY_OR: &str = "(define-syntax my-or
+                           (syntax-rules ()
+                             ((_) #f)
+                             ((_ e) e)
+                             ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))";
+
+    #[test]
+    fn syntax_rules_picks_the_first_matching_rule() {
+        assert_eq!(shows(&format!("{} (my-or)", MY_OR)), "#f");
+        assert_eq!(shows(&format!("{} (my-or 1)", MY_OR)), "1");
+        assert_eq!(shows(&format!("{} (my-or #f #f 3)", MY_OR)), "3");
+    }
+
+    #[test]
+    fn syntax_rules_free_symbols_arent_captured_by_user_bindings() {
+        assert_eq!(
+            shows(
+                "(define-syntax inc! (syntax-rules () ((_ v) (set! v (+ v 1)))))
+                 (define x 1)
+                 (let ((+ -)) (inc! x))
+                 x"
+            ),
+            "2"
+        );
+        assert_eq!(
+            shows(
+                "(define-syntax my-if (syntax-rules () ((_ c a b) (if c a b))))
+                 (let ((if list)) (my-if #t 1 2))"
+            ),
+            "1"
+        );
+        assert_eq!(
+            shows(
+                "(define (make-counter)
+                   (define n 0)
+                   (define-syntax bump! (syntax-rules () ((_) (set! n (+ n 1)))))
+                   (lambda () (let ((n 100)) (bump!)) n))
+                 (define c (make-counter))
+                 (c)
+                 (c)"
+            ),
+            "2"
+        );
+    }
+
+    #[test]
+    fn syntax_rules_bindings_dont_capture_user_variables() {
+        assert_eq!(
+            shows(&format!(
+                "{} (define tmp 1) (define y 2) (swap! tmp y) (list tmp y)",
+                SWAP
+            )),
+            "(2 1)"
+        );
+        assert_eq!(shows(&format!("{} (define t 5) (my-or #f t)", MY_OR)), "5");
+        assert!(matches!(
+            eval("(define-syntax def-x (syntax-rules () ((_ v) (define x v)))) (def-x 1) x"),
+            Err(RiskError::Unbound(..))
+        ));
+    }
+
+    #[test]
+    fn syntax_rules_keyword_parameters_bind_their_renamed_names() {
+        let m = "(define-syntax m (syntax-rules () ((_) ((lambda (#:k 1) k)))))";
+
+        assert_eq!(shows(&format!("(define k 99) {} (m)", m)), "1");
+        assert_eq!(shows(&format!("{} (m)", m)), "1");
+        assert_eq!(
+            shows(
+                "(define-syntax call-f (syntax-rules () ((_ v) (f #:k v))))
+                 (define (f #:k 0) (list k '#:k)) (call-f 2)"
+            ),
+            "(2 #:k)"
+        );
+        assert_eq!(
+            shows(
+                "(define-syntax def-g (syntax-rules () ((_ g) (define (g #:k 0) k))))
+                 (def-g g) (g #:k 3)"
+            ),
+            "3"
+        );
+    }
+
+    #[test]
+    fn syntax_rules_matches_ellipses() {
+        assert_eq!(
+            shows(
+                "(define-syntax my-let*
+                   (syntax-rules ()
+                     ((_ () body ...) (let () body ...))
+                     ((_ ((x v) rest ...) body ...) (let ((x v)) (my-let* (rest ...) body ...)))))
+                 (my-let* ((a 1) (b (+ a 1))) (list a b))"
+            ),
+            "(1 2)"
+        );
+        assert_eq!(
+            shows(
+                "(define-syntax pairs (syntax-rules () ((_ (k v) ...) '((k . v) ...))))
+                 (pairs (a 1) (b 2))"
+            ),
+            "((a . 1) (b . 2))"
+        );
+        assert_eq!(
+            shows(
+                "(define-syntax last-of (syntax-rules () ((_ x ... y) 'y)))
+                 (last-of 1 2 3)"
+            ),
+            "3"
+        );
+        assert_eq!(
+            shows(
+                "(define-syntax rest-of (syntax-rules () ((_ x . r) 'r)))
+                 (rest-of 1 2 3)"
+            ),
+            "(2 3)"
+        );
+    }
+
+    #[test]
+    fn syntax_rules_literals_only_match_themselves() {
+        let my_if = "(define-syntax my-if
+                       (syntax-rules (then else)
+                         ((_ c then t else e) (cond (c t) (else e)))))";
+
+        assert_eq!(shows(&format!("{} (my-if #f then 1 else 2)", my_if)), "2");
+        assert!(matches!(
+            eval(&format!("{} (my-if #f 1 2 3 4)", my_if)),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn syntax_rules_errors() {
+        assert!(matches!(
+            eval("(define-syntax m 1)"),
+            Err(RiskError::Type(..))
+        ));
+        assert!(matches!(
+            eval("(syntax-rules x)"),
+            Err(RiskError::Syntax(..))
+        ));
+        assert!(matches!(
+            eval("(syntax-rules () (x y))"),
+            Err(RiskError::Syntax(..))
+        ));
+        assert!(matches!(
+            eval("(define-syntax m (syntax-rules () ((_ x ...) x))) (m 1 2)"),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn syntax_rules_expands_with_macroexpand() {
+        assert_eq!(
+            shows(&format!("{} (macroexpand '(swap! x y))", SWAP)),
+            "(let ((tmp x)) (set! x y) (set! y tmp))"
+        );
+    }
+}
@@ -0,0 +1,513 @@
+use std::cmp::Ordering;
+
+use crate::lang::{
+    ast::AstNode,
+    base::{check_arity, check_min_arity, eval_arg},
+    scope::Scope,
+    types::{
+        error::{RiskError, RiskResult},
+        primitive::Primitive,
+        result::Result,
+    },
+};
+
+// Evaluates every argument, which all have to be ints or floats
+fn numbers(name: &str, args: &[AstNode], scope: &mut Scope) -> RiskResult<Vec<Primitive>> {
+    args.iter()
+        .map(|arg| match eval_arg(arg, scope)? {
+            Result::Primitive(p @ (Primitive::I(_) | Primitive::F(_))) => Ok(p),
+            value => Err(RiskError::Type(
+                format!("<{}> expected a number, received {}", name, value),
+                Some(arg.span().clone()),
+            )),
+        })
+        .collect()
+}
+
+// Evaluates every argument, which all have to be ints
+fn integers(name: &str, args: &[AstNode], scope: &mut Scope) -> RiskResult<Vec<i32>> {
+    args.iter()
+        .map(|arg| match eval_arg(arg, scope)? {
+            Result::Primitive(Primitive::I(i)) => Ok(i),
+            value => Err(RiskError::Type(
+                format!("<{}> expected an integer, received {}", name, value),
+                Some(arg.span().clone()),
+            )),
+        })
+        .collect()
+}
+
+fn as_float(n: &Primitive) -> f64 {
+    match n {
+        Primitive::I(i) => *i as f64,
+        Primitive::F(f) => *f,
+        _ => unreachable!("only called on numbers"),
+    }
+}
+
+fn overflow(name: &str) -> RiskError {
+    RiskError::Runtime(format!("Integer overflow in <{}>", name), None)
+}
+
+fn division_by_zero(name: &str) -> RiskError {
+    RiskError::Runtime(format!("Division by zero in <{}>", name), None)
+}
+
+fn is_zero(n: &Primitive) -> bool {
+    as_float(n) == 0.0
+}
+
+// Applies an operation to two numbers. Ints stay ints unless either side is a
+// float, in which case both are promoted.
+fn combine(
+    name: &str,
+    l: Primitive,
+    r: Primitive,
+    int_op: fn(i32, i32) -> Option<i32>,
+    float_op: fn(f64, f64) -> f64,
+) -> RiskResult<Primitive> {
+    match (&l, &r) {
+        (Primitive::I(a), Primitive::I(b)) => int_op(*a, *b)
+            .map(Primitive::I)
+            .ok_or_else(|| overflow(name)),
+        _ => Ok(Primitive::F(float_op(as_float(&l), as_float(&r)))),
+    }
+}
+
+fn fold(
+    name: &str,
+    init: Primitive,
+    nums: Vec<Primitive>,
+    int_op: fn(i32, i32) -> Option<i32>,
+    float_op: fn(f64, f64) -> f64,
+) -> RiskResult<Option<Result>> {
+    let total = nums
+        .into_iter()
+        .try_fold(init, |acc, n| combine(name, acc, n, int_op, float_op))?;
+
+    Ok(Some(Result::Primitive(total)))
+}
+
+// Division that stays an int when it divides evenly and becomes a float when
+// it doesn't
+fn divide(l: Primitive, r: Primitive) -> RiskResult<Primitive> {
+    if is_zero(&r) {
+        return Err(division_by_zero("/"));
+    }
+
+    match (&l, &r) {
+        (Primitive::I(a), Primitive::I(b)) if a.checked_rem(*b) == Some(0) => a
+            .checked_div(*b)
+            .map(Primitive::I)
+            .ok_or_else(|| overflow("/")),
+        _ => Ok(Primitive::F(as_float(&l) / as_float(&r))),
+    }
+}
+
+/*
+* (+) => 0
+* (+ 1 2 3) => 6
+* (+ 1 2.5) => 3.5
+*/
+pub fn adddef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    let nums = numbers("+", &args, scope)?;
+
+    fold("+", Primitive::I(0), nums, i32::checked_add, |a, b| a + b)
+}
+
+/*
+* (*) => 1
+* (* 2 3 4) => 24
+*/
+pub fn muldef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    let nums = numbers("*", &args, scope)?;
+
+    fold("*", Primitive::I(1), nums, i32::checked_mul, |a, b| a * b)
+}
+
+/*
+* (- 5) => -5
+* (- 10 1 2) => 7
+*/
+pub fn subdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_min_arity("-", &args, 1)?;
+    let mut nums = numbers("-", &args, scope)?;
+
+    match nums.len() {
+        1 => fold("-", Primitive::I(0), nums, i32::checked_sub, |a, b| a - b),
+        _ => {
+            let first = nums.remove(0);
+            fold("-", first, nums, i32::checked_sub, |a, b| a - b)
+        }
+    }
+}
+
+/*
+* Ints stay ints when they divide evenly, otherwise the result is a float. Use
+* quotient for truncating integer division.
+*
+* (/ 2) => 0.5
+* (/ 12 2 3) => 2
+* (/ 7 2) => 3.5
+*/
+pub fn divdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_min_arity("/", &args, 1)?;
+    let mut nums = numbers("/", &args, scope)?;
+
+    let (first, rest) = match nums.len() {
+        1 => (Primitive::I(1), nums),
+        _ => (nums.remove(0), nums),
+    };
+
+    let total = rest.into_iter().try_fold(first, divide)?;
+
+    Ok(Some(Result::Primitive(total)))
+}
+
+// Applies an integer division style operation to exactly two ints
+fn int_division(
+    name: &str,
+    args: Vec<AstNode>,
+    scope: &mut Scope,
+    op: fn(i32, i32) -> Option<i32>,
+) -> RiskResult<Option<Result>> {
+    check_arity(name, &args, 2)?;
+
+    let ints = integers(name, &args, scope)?;
+    if ints[1] == 0 {
+        return Err(division_by_zero(name).with_span(args[1].span()));
+    }
+
+    match op(ints[0], ints[1]) {
+        Some(i) => Ok(Some(Result::Primitive(Primitive::I(i)))),
+        None => Err(overflow(name)),
+    }
+}
+
+/*
+* Integer division truncating towards zero
+*
+* (quotient 7 2) => 3
+* (quotient -7 2) => -3
+*/
+pub fn quotientdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    int_division("quotient", args, scope, i32::checked_div)
+}
+
+/*
+* Remainder of quotient, taking the sign of the dividend
+*
+* (remainder -7 2) => -1
+*/
+pub fn remainderdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    int_division("remainder", args, scope, i32::checked_rem)
+}
+
+/*
+* Remainder of floored division, taking the sign of the divisor
+*
+* (modulo -7 2) => 1
+*/
+pub fn modulodef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    int_division("modulo", args, scope, |a, b| {
+        a.checked_rem(b).map(|r| {
+            if r != 0 && (r < 0) != (b < 0) {
+                r + b
+            } else {
+                r
+            }
+        })
+    })
+}
+
+// Numeric ordering of two numbers, None when either is NaN
+fn compare(l: &Primitive, r: &Primitive) -> Option<Ordering> {
+    match (l, r) {
+        (Primitive::I(a), Primitive::I(b)) => Some(a.cmp(b)),
+        _ => as_float(l).partial_cmp(&as_float(r)),
+    }
+}
+
+// True when every neighbouring pair of numbers is ordered as accepted allows
+fn chain(
+    name: &str,
+    args: Vec<AstNode>,
+    scope: &mut Scope,
+    accepted: fn(Ordering) -> bool,
+) -> RiskResult<Option<Result>> {
+    check_min_arity(name, &args, 1)?;
+
+    let nums = numbers(name, &args, scope)?;
+    let holds = nums
+        .windows(2)
+        .all(|w| compare(&w[0], &w[1]).is_some_and(accepted));
+
+    Ok(Some(Result::Primitive(Primitive::B(holds))))
+}
+
+/*
+* Numeric equality, ints and floats compare by value
+*
+* (= 1 1.0) => #t
+* (= 1 1 2) => #f
+*/
+pub fn numeqdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    chain("=", args, scope, Ordering::is_eq)
+}
+
+/*
+* (< 1 2 3) => #t
+* (< 1 3 2) => #f
+*/
+pub fn ltdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    chain("<", args, scope, Ordering::is_lt)
+}
+
+pub fn gtdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    chain(">", args, scope, Ordering::is_gt)
+}
+
+pub fn ltedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    chain("<=", args, scope, Ordering::is_le)
+}
+
+pub fn gtedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    chain(">=", args, scope, Ordering::is_ge)
+}
+
+// Tests a single number against zero
+fn sign(
+    name: &str,
+    args: Vec<AstNode>,
+    scope: &mut Scope,
+    accepted: fn(Ordering) -> bool,
+) -> RiskResult<Option<Result>> {
+    check_arity(name, &args, 1)?;
+
+    let n = numbers(name, &args, scope)?.remove(0);
+    let holds = compare(&n, &Primitive::I(0)).is_some_and(accepted);
+
+    Ok(Some(Result::Primitive(Primitive::B(holds))))
+}
+
+pub fn zerohuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    sign("zero?", args, scope, Ordering::is_eq)
+}
+
+pub fn positivehuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    sign("positive?", args, scope, Ordering::is_gt)
+}
+
+pub fn negativehuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    sign("negative?", args, scope, Ordering::is_lt)
+}
+
+pub fn evenhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("even?", &args, 1)?;
+
+    let i = integers("even?", &args, scope)?[0];
+
+    Ok(Some(Result::Primitive(Primitive::B(i % 2 == 0))))
+}
+
+pub fn oddhuhdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("odd?", &args, 1)?;
+
+    let i = integers("odd?", &args, scope)?[0];
+
+    Ok(Some(Result::Primitive(Primitive::B(i % 2 != 0))))
+}
+
+// Picks the number that wins against every other. Like arithmetic, the result
+// is a float if any argument was.
+fn extreme(
+    name: &str,
+    args: Vec<AstNode>,
+    scope: &mut Scope,
+    wins: Ordering,
+) -> RiskResult<Option<Result>> {
+    check_min_arity(name, &args, 1)?;
+
+    let nums = numbers(name, &args, scope)?;
+    let any_float = nums.iter().any(|n| matches!(n, Primitive::F(_)));
+    let best = nums
+        .into_iter()
+        .reduce(|best, n| {
+            if compare(&n, &best) == Some(wins) {
+                n
+            } else {
+                best
+            }
+        })
+        .expect("at least one number");
+
+    Ok(Some(Result::Primitive(if any_float {
+        Primitive::F(as_float(&best))
+    } else {
+        best
+    })))
+}
+
+/*
+* (min 3 1 2) => 1
+* (min 1 2.0) => 1.0
+*/
+pub fn mindef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    extreme("min", args, scope, Ordering::Less)
+}
+
+pub fn maxdef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    extreme("max", args, scope, Ordering::Greater)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::eval;
+
+    fn value(source: &str) -> Result {
+        eval(source).unwrap().unwrap()
+    }
+
+    fn int(i: i32) -> Result {
+        Result::Primitive(Primitive::I(i))
+    }
+
+    fn float(f: f64) -> Result {
+        Result::Primitive(Primitive::F(f))
+    }
+
+    #[test]
+    fn addition() {
+        assert_eq!(value("(+)"), int(0));
+        assert_eq!(value("(+ 1 2 3)"), int(6));
+        assert_eq!(value("(+ 1 2.5)"), float(3.5));
+        assert_eq!(value("(+ 0.5 0.25)"), float(0.75));
+    }
+
+    #[test]
+    fn subtraction() {
+        assert_eq!(value("(- 5)"), int(-5));
+        assert_eq!(value("(- 10 1 2)"), int(7));
+        assert_eq!(value("(- 1 0.5)"), float(0.5));
+        assert!(matches!(eval("(-)"), Err(RiskError::Arity(..))));
+    }
+
+    #[test]
+    fn multiplication() {
+        assert_eq!(value("(*)"), int(1));
+        assert_eq!(value("(* 2 3 4)"), int(24));
+        assert_eq!(value("(* 2 1.5)"), float(3.0));
+    }
+
+    #[test]
+    fn division() {
+        assert_eq!(value("(/ 12 2 3)"), int(2));
+        assert_eq!(value("(/ 7 2)"), float(3.5));
+        assert_eq!(value("(/ 2)"), float(0.5));
+        assert_eq!(value("(/ 1.0 4)"), float(0.25));
+    }
+
+    #[test]
+    fn integer_division() {
+        assert_eq!(value("(quotient 7 2)"), int(3));
+        assert_eq!(value("(quotient -7 2)"), int(-3));
+        assert_eq!(value("(remainder 7 2)"), int(1));
+        assert_eq!(value("(remainder -7 2)"), int(-1));
+        assert_eq!(value("(modulo -7 2)"), int(1));
+        assert_eq!(value("(modulo 7 -2)"), int(-1));
+        assert_eq!(value("(modulo 6 3)"), int(0));
+        assert!(matches!(eval("(quotient 7.0 2)"), Err(RiskError::Type(..))));
+    }
+
+    #[test]
+    fn division_by_zero_errors() {
+        for source in [
+            "(/ 1 0)",
+            "(/ 1.5 0.0)",
+            "(/ 0)",
+            "(quotient 1 0)",
+            "(remainder 1 0)",
+            "(modulo 1 0)",
+        ] {
+            match eval(source) {
+                Err(RiskError::Runtime(msg, _)) => assert!(msg.contains("Division by zero")),
+                other => panic!("expected division by zero for {}, got {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_errors() {
+        assert!(matches!(
+            eval("(+ 2147483647 1)"),
+            Err(RiskError::Runtime(..))
+        ));
+        assert!(matches!(
+            eval("(quotient -2147483648 -1)"),
+            Err(RiskError::Runtime(..))
+        ));
+    }
+
+    #[test]
+    fn non_numbers_are_type_errors() {
+        assert!(matches!(eval("(+ 1 #t)"), Err(RiskError::Type(..))));
+        assert!(matches!(eval(r#"(* "a" 2)"#), Err(RiskError::Type(..))));
+    }
+
+    fn bool(b: bool) -> Result {
+        Result::Primitive(Primitive::B(b))
+    }
+
+    #[test]
+    fn numeric_equality() {
+        assert_eq!(value("(= 1 1.0)"), bool(true));
+        assert_eq!(value("(= 2 2 2)"), bool(true));
+        assert_eq!(value("(= 1 1 2)"), bool(false));
+        assert_eq!(value("(= 1)"), bool(true));
+        assert!(matches!(eval("(=)"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(= 1 'a)"), Err(RiskError::Type(..))));
+    }
+
+    #[test]
+    fn chained_ordering() {
+        assert_eq!(value("(< 1 2 3)"), bool(true));
+        assert_eq!(value("(< 1 3 2)"), bool(false));
+        assert_eq!(value("(< 1 1)"), bool(false));
+        assert_eq!(value("(<= 1 1 2.5)"), bool(true));
+        assert_eq!(value("(> 3 2.5 1)"), bool(true));
+        assert_eq!(value("(> 3 3)"), bool(false));
+        assert_eq!(value("(>= 3 3 -1)"), bool(true));
+        assert_eq!(value("(>= 1 2)"), bool(false));
+    }
+
+    #[test]
+    fn sign_predicates() {
+        assert_eq!(value("(zero? 0)"), bool(true));
+        assert_eq!(value("(zero? 0.0)"), bool(true));
+        assert_eq!(value("(zero? 1)"), bool(false));
+        assert_eq!(value("(positive? 0.5)"), bool(true));
+        assert_eq!(value("(positive? 0)"), bool(false));
+        assert_eq!(value("(negative? -3)"), bool(true));
+        assert_eq!(value("(negative? 3)"), bool(false));
+        assert!(matches!(eval("(zero? #f)"), Err(RiskError::Type(..))));
+    }
+
+    #[test]
+    fn parity_predicates() {
+        assert_eq!(value("(even? 4)"), bool(true));
+        assert_eq!(value("(even? -3)"), bool(false));
+        assert_eq!(value("(odd? -3)"), bool(true));
+        assert_eq!(value("(odd? 0)"), bool(false));
+        assert!(matches!(eval("(odd? 1.0)"), Err(RiskError::Type(..))));
+    }
+
+    #[test]
+    fn min_and_max() {
+        assert_eq!(value("(min 3 1 2)"), int(1));
+        assert_eq!(value("(max 3 1 2)"), int(3));
+        assert_eq!(value("(min 1 2.0)"), float(1.0));
+        assert_eq!(value("(max 1.5 2)"), float(2.0));
+        assert!(matches!(eval("(max)"), Err(RiskError::Arity(..))));
+    }
+}
@@ -1,2 +1,92 @@
+use crate::lang::{
+    ast::AstNode,
+    exec::{Program, Tail},
+    scope::Scope,
+    types::{
+        error::{RiskError, RiskResult},
+        result::Result,
+    },
+};
+
+pub mod binding;
+pub mod control;
 pub mod definition;
+pub mod exception;
+pub mod list;
 pub mod logic;
+pub mod macros;
+pub mod math;
+pub mod quote;
+
+// Errors unless exactly expected arguments were passed to the builtin name
+pub fn check_arity(name: &str, args: &[AstNode], expected: usize) -> RiskResult<()> {
+    match args.len() == expected {
+        true => Ok(()),
+        false => Err(arity_error(name, &expected.to_string(), args.len())),
+    }
+}
+
+// Errors unless at least min arguments were passed to the builtin name
+pub fn check_min_arity(name: &str, args: &[AstNode], min: usize) -> RiskResult<()> {
+    match args.len() >= min {
+        true => Ok(()),
+        false => Err(arity_error(name, &format!("at least {}", min), args.len())),
+    }
+}
+
+// Errors unless min to max arguments were passed to the builtin name
+pub fn check_arity_range(name: &str, args: &[AstNode], min: usize, max: usize) -> RiskResult<()> {
+    let expected = match max - min {
+        1 => format!("{} or {}", min, max),
+        _ => format!("{} to {}", min, max),
+    };
+
+    match (min..=max).contains(&args.len()) {
+        true => Ok(()),
+        false => Err(arity_error(name, &expected, args.len())),
+    }
+}
+
+fn arity_error(name: &str, expected: &str, received: usize) -> RiskError {
+    RiskError::Arity(
+        format!(
+            "Incorrect number of arguments to function <{}>, expected {}, received {}",
+            name, expected, received
+        ),
+        None,
+    )
+}
+
+// Evaluates an argument which must produce a value
+pub fn eval_arg(arg: &AstNode, scope: &mut Scope) -> RiskResult<Result> {
+    match Program::new(arg.clone(), scope).exec()? {
+        Some(r) => Ok(r),
+        None => Err(RiskError::Runtime(
+            "Argument evaluated to nothing".to_string(),
+            Some(arg.span().clone()),
+        )),
+    }
+}
+
+pub fn eval_args(args: &[AstNode], scope: &mut Scope) -> RiskResult<Vec<Result>> {
+    args.iter().map(|arg| eval_arg(arg, scope)).collect()
+}
+
+// Evaluates expressions in order, producing the value of the last one
+pub fn eval_sequence(exprs: &[AstNode], scope: &mut Scope) -> RiskResult<Option<Result>> {
+    exprs
+        .iter()
+        .try_fold(None, |_, expr| Program::new(expr.clone(), scope).exec())
+}
+
+// Like eval_sequence, but leaves the last expression to be evaluated in tail
+// position
+pub fn tail_sequence(exprs: &[AstNode], scope: &mut Scope) -> RiskResult<Tail> {
+    match exprs.split_last() {
+        Some((last, init)) => {
+            eval_sequence(init, scope)?;
+            Ok(Tail::Eval(last.clone(), scope.clone()))
+        }
+        None => Ok(Tail::Value(None)),
+    }
+}
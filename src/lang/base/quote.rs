@@ -0,0 +1,343 @@
+use crate::lang::{
+    ast::AstNode,
+    base::{check_arity, eval_arg},
+    exec::Nested,
+    scope::{Scope, original_name},
+    token::Span,
+    types::{
+        error::{RiskError, RiskResult},
+        pair,
+        primitive::Primitive,
+        result::Result,
+    },
+};
+
+// Converts code into the data it reads as. Numbers, booleans, strings and
+// keywords are themselves, any other leaf is a symbol and lists become lists, with
+// (a . b) read as a dotted pair. Symbols and keywords renamed by a macro
+// expansion read as the name they were written as
+pub fn datum(node: &AstNode) -> RiskResult<Result> {
+    let _nested = Nested::enter(node.span())?;
+
+    match node {
+        AstNode::Leaf(l, _) => Ok(Result::Primitive(match original_name(l) {
+            "#t" => Primitive::B(true),
+            "#f" => Primitive::B(false),
+            _ => match l.parse::<i32>() {
+                Ok(i) => Primitive::I(i),
+                Err(_) => match l.parse::<f64>() {
+                    Ok(f) => Primitive::F(f),
+                    Err(_) => Primitive::Sym(original_name(l).to_string()),
+                },
+            },
+        })),
+        AstNode::Str(s, _) => Ok(Result::Primitive(Primitive::S(s.to_string()))),
+        AstNode::Keyword(k, _) => Ok(Result::Primitive(Primitive::Kw(
+            original_name(k).to_string(),
+        ))),
+        AstNode::AST(nodes, span) => {
+            let is_dot = |n: &AstNode| matches!(n, AstNode::Leaf(l, _) if l == ".");
+
+            match &nodes[..] {
+                [init @ .., dot, tail] if is_dot(dot) && !init.is_empty() => {
+                    if init.iter().any(is_dot) {
+                        return Err(RiskError::Syntax(
+                            "unexpected '.' in dotted list".to_string(),
+                            Some(span.clone()),
+                        ));
+                    }
+
+                    Ok(pair::list_with_tail(
+                        init.iter().map(datum).collect::<RiskResult<_>>()?,
+                        datum(tail)?,
+                    ))
+                }
+                _ if nodes.iter().any(is_dot) => Err(RiskError::Syntax(
+                    "'.' must come second to last in a dotted list".to_string(),
+                    Some(span.clone()),
+                )),
+                _ => Ok(pair::list(
+                    nodes.iter().map(datum).collect::<RiskResult<_>>()?,
+                )),
+            }
+        }
+    }
+}
+
+// Converts data back into the code it would read as, the inverse of datum.
+// Every node is given span, as there is no source for it to point at
+pub fn code(value: &Result, span: &Span) -> RiskResult<AstNode> {
+    let _nested = Nested::enter(span)?;
+    let leaf = |text: String| Ok(AstNode::Leaf(text, span.clone()));
+
+    match value {
+        Result::Primitive(p) => match p {
+            Primitive::I(i) => leaf(i.to_string()),
+            // Debug keeps the decimal point so it reads back as a float
+            Primitive::F(f) => leaf(format!("{:?}", f)),
+            Primitive::B(true) => leaf("#t".to_string()),
+            Primitive::B(false) => leaf("#f".to_string()),
+            Primitive::S(s) => Ok(AstNode::Str(s.to_string(), span.clone())),
+            Primitive::Sym(s) => leaf(s.to_string()),
+            Primitive::Kw(k) => Ok(AstNode::Keyword(k.to_string(), span.clone())),
+        },
+        Result::Nil => Ok(AstNode::AST(Vec::new().into(), span.clone())),
+        Result::Pair(_) => {
+            let mut nodes = Vec::new();
+            let mut rest = value;
+            while let Result::Pair(pair) = rest {
+                nodes.push(code(&pair.car, span)?);
+                rest = &pair.cdr;
+            }
+
+            if *rest != Result::Nil {
+                nodes.push(AstNode::Leaf(".".to_string(), span.clone()));
+                nodes.push(code(rest, span)?);
+            }
+
+            Ok(AstNode::AST(nodes.into(), span.clone()))
+        }
+        _ => Err(RiskError::Type(
+            format!("Cannot use {} as code", value),
+            Some(span.clone()),
+        )),
+    }
+}
+
+/*
+* Returns its argument as data without evaluating it
+*
+* (quote x) => x
+* (quote (1 (a b))) => (1 (a b))
+* 'x => x
+*/
+pub fn quotedef(args: Vec<AstNode>, _scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("quote", &args, 1)?;
+
+    Ok(Some(datum(&args[0])?))
+}
+
+// The argument of node if it is the form (name arg)
+fn form_arg<'a>(node: &'a AstNode, name: &str) -> Option<&'a AstNode> {
+    match node {
+        AstNode::AST(nodes, _) => match &nodes[..] {
+            [AstNode::Leaf(head, _), arg] if original_name(head) == name => Some(arg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn symbol(name: &str) -> Result {
+    Result::Primitive(Primitive::Sym(name.to_string()))
+}
+
+// Builds the data for a quasiquote template, evaluating anything unquoted at
+// depth 1. Nested quasiquotes increase the depth and unquotes decrease it, so
+// only the outermost template has its holes filled in.
+fn quasi(node: &AstNode, depth: usize, scope: &mut Scope) -> RiskResult<Result> {
+    let _nested = Nested::enter(node.span())?;
+
+    if let Some(arg) = form_arg(node, "unquote") {
+        return match depth {
+            1 => eval_arg(arg, scope),
+            _ => Ok(pair::list(
+                [
+                    vec![symbol("unquote")],
+                    quasi_items(std::slice::from_ref(arg), depth - 1, scope)?,
+                ]
+                .concat(),
+            )),
+        };
+    }
+
+    if let Some(arg) = form_arg(node, "quasiquote") {
+        return Ok(pair::list(vec![
+            symbol("quasiquote"),
+            quasi(arg, depth + 1, scope)?,
+        ]));
+    }
+
+    match node {
+        AstNode::AST(nodes, _) => match &nodes[..] {
+            [init @ .., AstNode::Leaf(dot, _), tail] if dot == "." && !init.is_empty() => Ok(
+                pair::list_with_tail(quasi_items(init, depth, scope)?, quasi(tail, depth, scope)?),
+            ),
+            _ => Ok(pair::list(quasi_items(nodes, depth, scope)?)),
+        },
+        _ => datum(node),
+    }
+}
+
+// Builds the elements of a list in a quasiquote template, splicing in anything
+// marked with unquote-splicing at depth 1
+fn quasi_items(items: &[AstNode], depth: usize, scope: &mut Scope) -> RiskResult<Vec<Result>> {
+    let mut built = Vec::new();
+
+    for item in items {
+        match form_arg(item, "unquote-splicing") {
+            Some(arg) if depth == 1 => {
+                let spliced = eval_arg(arg, scope)?;
+                built.extend(pair::to_vec(&spliced).ok_or_else(|| {
+                    RiskError::Type(
+                        format!("unquote-splicing expected a list, received {}", spliced),
+                        Some(arg.span().clone()),
+                    )
+                })?);
+            }
+            Some(arg) => built.push(pair::list(
+                [
+                    vec![symbol("unquote-splicing")],
+                    quasi_items(std::slice::from_ref(arg), depth - 1, scope)?,
+                ]
+                .concat(),
+            )),
+            None => built.push(quasi(item, depth, scope)?),
+        }
+    }
+
+    Ok(built)
+}
+
+/*
+* Like quote, but expressions marked with unquote are evaluated and those marked
+* with unquote-splicing are evaluated and spliced into the surrounding list
+*
+* (define b 2)
+* `(a ,b ,@(list 3 4)) => (a 2 3 4)
+*/
+pub fn quasiquotedef(args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    check_arity("quasiquote", &args, 1)?;
+
+    Ok(Some(quasi(&args[0], 1, scope)?))
+}
+
+pub fn unquotedef(_args: Vec<AstNode>, _scope: &mut Scope) -> RiskResult<Option<Result>> {
+    Err(RiskError::Syntax(
+        "unquote used outside of quasiquote".to_string(),
+        None,
+    ))
+}
+
+pub fn unquotesplicingdef(_args: Vec<AstNode>, _scope: &mut Scope) -> RiskResult<Option<Result>> {
+    Err(RiskError::Syntax(
+        "unquote-splicing used outside of quasiquote".to_string(),
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::{eval, shows};
+    use crate::lang::{ast, token};
+
+    #[test]
+    fn quote_returns_symbols() {
+        assert_eq!(
+            eval("(quote x)").unwrap().unwrap(),
+            Result::Primitive(Primitive::Sym("x".to_string()))
+        );
+        assert_eq!(shows("'undefined-anywhere"), "undefined-anywhere");
+    }
+
+    #[test]
+    fn quote_self_evaluating_data() {
+        assert_eq!(
+            eval("'1.5").unwrap().unwrap(),
+            Result::Primitive(Primitive::F(1.5))
+        );
+        assert_eq!(shows("'#t"), "#t");
+        assert_eq!(shows(r#"'"s""#), r#""s""#);
+    }
+
+    #[test]
+    fn quote_lists() {
+        assert_eq!(shows("'()"), "()");
+        assert_eq!(shows("'(1 (a \"b\") #f)"), "(1 (a \"b\") #f)");
+        assert_eq!(shows("(car '(if x y))"), "if");
+        assert_eq!(shows("''a"), "(quote a)");
+    }
+
+    #[test]
+    fn quote_dotted_pairs() {
+        assert_eq!(shows("'(1 . 2)"), "(1 . 2)");
+        assert_eq!(shows("'(1 2 . (3))"), "(1 2 3)");
+        assert!(matches!(eval("'(. 2)"), Err(RiskError::Syntax(..))));
+        assert!(matches!(eval("'(1 . 2 3)"), Err(RiskError::Syntax(..))));
+    }
+
+    #[test]
+    fn quoted_symbols_compare_equal() {
+        assert_eq!(shows("(eq? 'a 'a)"), "#t");
+        assert_eq!(shows("(eq? 'a 'b)"), "#f");
+    }
+
+    #[test]
+    fn quote_takes_one_argument() {
+        assert!(matches!(eval("(quote)"), Err(RiskError::Arity(..))));
+        assert!(matches!(eval("(quote a b)"), Err(RiskError::Arity(..))));
+    }
+
+    #[test]
+    fn quasiquote_without_holes_is_quote() {
+        assert_eq!(shows("`(a (b 1) \"c\")"), "(a (b 1) \"c\")");
+        assert_eq!(shows("`x"), "x");
+    }
+
+    #[test]
+    fn quasiquote_fills_unquotes() {
+        assert_eq!(shows("(define b 2) `(a ,b ,(cons b b))"), "(a 2 (2 . 2))");
+        assert_eq!(shows("`,(car '(x))"), "x");
+    }
+
+    #[test]
+    fn quasiquote_splices() {
+        assert_eq!(
+            shows("(define rest '(3 4)) `(1 ,@rest 5 ,@'())"),
+            "(1 3 4 5)"
+        );
+        assert!(matches!(eval("`(1 ,@2)"), Err(RiskError::Type(..))));
+    }
+
+    #[test]
+    fn quasiquote_dotted_tail() {
+        assert_eq!(shows("(define b 2) `(1 . ,b)"), "(1 . 2)");
+    }
+
+    #[test]
+    fn nested_quasiquote_only_fills_outermost_level() {
+        assert_eq!(
+            shows("(define x 1) `(a `(b ,(c ,x)))"),
+            "(a (quasiquote (b (unquote (c 1)))))"
+        );
+        assert_eq!(
+            shows("(define xs '(1 2)) `(a `(b ,@,@xs))"),
+            "(a (quasiquote (b (unquote-splicing 1 2))))"
+        );
+    }
+
+    #[test]
+    fn unquote_outside_quasiquote_errors() {
+        assert!(matches!(eval(",x"), Err(RiskError::Syntax(..))));
+        assert!(matches!(eval(",@x"), Err(RiskError::Syntax(..))));
+    }
+
+    #[test]
+    fn code_round_trips_through_datum() {
+        for source in ["(a \"b\" 1 2.0 #t #:k (c . d) ())", "x", "'(1 ,@y)"] {
+            let node = ast::new(token::tokenize(source).unwrap())
+                .unwrap()
+                .remove(0);
+            let value = datum(&node).unwrap();
+
+            assert_eq!(code(&value, &Span::default()).unwrap(), node, "{}", source);
+        }
+
+        let f = Scope::base().get("car").unwrap();
+        assert!(matches!(
+            code(&f, &Span::default()),
+            Err(RiskError::Type(..))
+        ));
+    }
+}
@@ -1,68 +1,307 @@
+use std::{cell::Cell, rc::Rc};
+
+use crate::lang::base::{eval_args, macros};
+use crate::lang::token::Span;
+use crate::lang::trace::{self, Args, Frame};
+use crate::lang::types::builtin::{Builtin, BuiltinFn};
+use crate::lang::types::error::{RiskError, RiskResult};
 use crate::lang::types::result::Result;
-use crate::lang::{scope::Scope, types::primitive::Primitive};
+use crate::lang::types::userfunc::FnDef;
+use crate::lang::{
+    scope::{Scope, original_name},
+    types::primitive::Primitive,
+};
 
 use crate::lang::ast::AstNode;
 
+// The outcome of a form that can end in tail position
+#[derive(Debug)]
+pub enum Tail {
+    // Finished with a value
+    Value(Option<Result>),
+    // Still has an expression to evaluate in the given scope. The evaluator
+    // runs it in place of the form rather than recursing into it
+    Eval(AstNode, Scope),
+}
+
+// How deeply evaluation may nest. Calls outside tail position recurse on the
+// host stack, as do reading nested lists and converting between code and data,
+// so going deeper is an error rather than a crash. All of them count towards
+// the same limit, so a single stack size covers any mix of them
+pub const MAX_DEPTH: usize = 10_000;
+
+// Stack a thread needs for MAX_DEPTH levels of nesting. A level of evaluation
+// keeps frames for Program::exec, run and whichever builtin or user function
+// it calls, and unoptimised builds give every temporary in them its own slot.
+// The deepest, a procedure called by dynamic-wind, takes about 18 KiB a level
+// there and 4 KiB optimised, while reading a list takes 4 KiB at most. This
+// allows around 26 KiB a level, leaving room for anything that's added later
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+thread_local! {
+    // How many levels of nesting are in progress
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+// One level of nesting on the host stack, given back when dropped
+pub struct Nested(usize);
+
+impl Nested {
+    // Enters a level of nesting for the code at span, failing if there are
+    // already MAX_DEPTH in progress
+    pub fn enter(span: &Span) -> RiskResult<Nested> {
+        let nesting = NESTING.get();
+        if nesting >= MAX_DEPTH {
+            return Err(RiskError::Runtime(
+                "maximum recursion depth exceeded".to_string(),
+                Some(span.clone()),
+            ));
+        }
+
+        NESTING.set(nesting + 1);
+        Ok(Nested(nesting))
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        NESTING.set(self.0);
+    }
+}
+
 pub struct Program<'a> {
     scope: &'a mut Scope,
     ast: AstNode,
 }
 
 impl Program<'_> {
-    pub fn exec(&mut self) -> Option<Result> {
-        match &self.ast.clone() {
-            // Reference
-            // Try int -> float -> scope lookup (bools are defined in scope)
-            AstNode::Leaf(l) => match l.parse::<i32>() {
-                Ok(i) => Some(Result::Primitive(Primitive::I(i))),
-                Err(_) => match l.parse::<f64>() {
-                    Ok(f) => Some(Result::Primitive(Primitive::F(f))),
-                    Err(_) => match self.scope.map.get(l) {
-                        Some(s) => match s {
-                            Result::Primitive(r) => Some(Result::Primitive(r.clone())),
-                            Result::Builtin(f) => Some(Result::Builtin(f.clone())),
-                            Result::FnDef(fn_def) => Some(Result::FnDef(fn_def.clone())),
+    pub fn exec(&mut self) -> RiskResult<Option<Result>> {
+        let _nested = Nested::enter(self.ast.span())?;
+
+        // Calls made from here push their frames above depth, and they're
+        // dropped once evaluation has finished. An error leaves them in place
+        let depth = trace::depth();
+        let result = self.run(depth);
+        if result.is_ok() {
+            trace::truncate(depth);
+        }
+
+        result
+    }
+
+    fn run(&mut self, depth: usize) -> RiskResult<Option<Result>> {
+        let mut ast = self.ast.clone();
+        let mut scope = self.scope.clone();
+
+        // Calls in tail position replace ast and scope and go round again, so a
+        // chain of tail calls runs in constant Rust stack
+        loop {
+            let (next, next_scope) = match &ast {
+                // Reference
+                // Try bool -> int -> float -> scope lookup. Bools are literals so
+                // that they mean the same in every environment
+                AstNode::Leaf(l, span) => {
+                    return match original_name(l) {
+                        "#t" => Ok(Some(Result::Primitive(Primitive::B(true)))),
+                        "#f" => Ok(Some(Result::Primitive(Primitive::B(false)))),
+                        _ => match l.parse::<i32>() {
+                            Ok(i) => Ok(Some(Result::Primitive(Primitive::I(i)))),
+                            Err(_) => match l.parse::<f64>() {
+                                Ok(f) => Ok(Some(Result::Primitive(Primitive::F(f)))),
+                                Err(_) => match scope.get(l) {
+                                    Some(s) => Ok(Some(s)),
+                                    None => Err(RiskError::Unbound(
+                                        original_name(l).to_string(),
+                                        Some(span.clone()),
+                                    )),
+                                },
+                            },
                         },
-                        None => panic!("undefined scope for {}", l),
-                    },
-                },
-            },
-
-            // Function call
-            AstNode::AST(ast_nodes) => match &ast_nodes[..] {
-                [] => None,
-                [a, rest @ ..] => match Program::new(a.clone(), self.scope).exec() {
-                    Some(result) => match result {
-                        Result::Primitive(p) => {
-                            panic!("Call to value {} as a function", Result::Primitive(p))
+                    };
+                }
+
+                AstNode::Str(s, _) => {
+                    return Ok(Some(Result::Primitive(Primitive::S(s.to_string()))));
+                }
+
+                // Keywords evaluate to themselves
+                AstNode::Keyword(k, _) => {
+                    return Ok(Some(Result::Primitive(Primitive::Kw(
+                        original_name(k).to_string(),
+                    ))));
+                }
+
+                // Function call
+                AstNode::AST(ast_nodes, span) => match &ast_nodes[..] {
+                    [] => return Ok(None),
+                    [a, rest @ ..] => match Program::new(a.clone(), &mut scope).exec()? {
+                        // Macro calls are replaced by their expansion
+                        Some(Result::Macro(m)) => (
+                            macros::expand(&m, rest, span).map_err(|e| e.with_span(span))?,
+                            scope.clone(),
+                        ),
+                        Some(result) => {
+                            match call(result, ast_nodes, span, depth, &mut scope)
+                                .map_err(|e| e.with_span(span))?
+                            {
+                                Tail::Value(v) => return Ok(v),
+                                Tail::Eval(next, next_scope) => (next, next_scope),
+                            }
                         }
-                        Result::Builtin(f) => (f.f)(rest.to_vec(), self.scope),
-                        Result::FnDef(fn_def) => fn_def.exec(rest.to_vec(), self.scope),
+                        None => return Ok(None),
                     },
-                    None => None,
                 },
-            },
+            };
+
+            ast = next;
+            scope = next_scope;
         }
     }
 
     pub fn new(ast: AstNode, scope: &mut Scope) -> Program<'_> {
-        Program {
-            scope: scope,
-            ast: ast,
+        Program { scope, ast }
+    }
+}
+
+// Calls a function value with the unevaluated arguments of the call form,
+// leaving a function's body or a builtin's tail expression for the caller to
+// evaluate. The call's frame replaces whatever function frame is above depth, as
+// the call is made in tail position of it
+fn call(
+    f: Result,
+    form: &Rc<[AstNode]>,
+    span: &Span,
+    depth: usize,
+    scope: &mut Scope,
+) -> RiskResult<Tail> {
+    let args = &form[1..];
+
+    match f {
+        Result::Builtin(b) => {
+            let frame = Frame::builtin(&b, Some(span), Args::Forms(form.clone()));
+            call_builtin(&b, args, frame, scope)
         }
+        Result::FnDef(fn_def) => {
+            let (values, keywords) = fn_def.eval_args(args, scope)?;
+
+            trace::truncate(depth);
+            trace::push(Frame::function(&fn_def, Some(span), &values, &keywords));
+            fn_def.prepare_keywords(values, keywords)
+        }
+        Result::Continuation(id) => escape(id, eval_args(args, scope)?),
+        value => Err(RiskError::Type(
+            format!("Call to value {} as a function", value),
+            None,
+        )),
     }
 }
 
-pub fn exec(exprs: Vec<AstNode>, scope: Option<&mut Scope>) -> Option<Result> {
+// Runs a builtin under a frame of its own. When it hands back an expression
+// that's evaluated in the caller's frame, as the builtin is done with
+fn call_builtin(
+    b: &Builtin,
+    args: &[AstNode],
+    frame: Frame,
+    scope: &mut Scope,
+) -> RiskResult<Tail> {
+    let depth = trace::depth();
+    trace::push(frame);
+
+    let tail = match b.f {
+        BuiltinFn::Value(f) => Tail::Value(f(args.to_vec(), scope)?),
+        BuiltinFn::Tail(f) => f(args.to_vec(), scope)?,
+    };
+
+    // Leave any frame the builtin pushed for a function it called in tail
+    // position, like apply does
+    trace::remove(depth);
+    Ok(tail)
+}
+
+// Calls the continuation id, unwinding to its call/cc with the value passed
+fn escape(id: usize, mut args: Vec<Result>) -> RiskResult<Tail> {
+    match args.len() {
+        0 | 1 => Err(RiskError::Escape(id, Box::new(args.pop()))),
+        n => Err(RiskError::Arity(
+            format!(
+                "Incorrect number of arguments provided. Expected 0 to 1, received {}",
+                n
+            ),
+            None,
+        )),
+    }
+}
+
+// Finishes off a tail, evaluating whatever expression it has left
+pub fn resolve(tail: Tail) -> RiskResult<Option<Result>> {
+    match tail {
+        Tail::Value(v) => Ok(v),
+        Tail::Eval(ast, mut scope) => Program::new(ast, &mut scope).exec(),
+    }
+}
+
+// Calls a function value with arguments that have already been evaluated
+pub fn apply(f: Result, args: Vec<Result>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+    let depth = trace::depth();
+    let result = resolve(apply_tail(f, args, scope)?)?;
+    trace::truncate(depth);
+
+    Ok(result)
+}
+
+// Like apply, but leaves the body of a called function for the caller to
+// evaluate so the call can be made in tail position
+pub fn apply_tail(f: Result, args: Vec<Result>, scope: &mut Scope) -> RiskResult<Tail> {
+    match f {
+        Result::FnDef(fn_def) => {
+            let (values, keywords) = FnDef::split_keywords(args)?;
+            trace::push(Frame::function(&fn_def, None, &values, &keywords));
+            fn_def.prepare_keywords(values, keywords)
+        }
+        Result::Continuation(id) => escape(id, args),
+        Result::Builtin(b) if b.form => Err(RiskError::Type(
+            format!("Cannot apply special form <{}> to values", b.id),
+            None,
+        )),
+        Result::Builtin(b) => {
+            let frame = Frame::builtin(&b, None, Args::Values(args.clone()));
+
+            // Builtins evaluate their own arguments, so bind the values in a scope
+            // of their own under names that can't be written in source and pass
+            // references to those instead
+            let mut arg_scope = scope.child();
+            let refs = args
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    let name = format!(" arg{}", i);
+                    arg_scope.define(&name, value);
+                    AstNode::leaf(&name)
+                })
+                .collect::<Vec<_>>();
+
+            call_builtin(&b, &refs, frame, &mut arg_scope)
+        }
+        value => Err(RiskError::Type(
+            format!("Call to value {} as a function", value),
+            None,
+        )),
+    }
+}
+
+pub fn exec(exprs: Vec<AstNode>, scope: Option<&mut Scope>) -> RiskResult<Option<Result>> {
+    // Starting from the top level, so nothing is being called
+    trace::truncate(0);
+
     match scope {
         Some(s) => exprs
             .iter()
-            .fold(None, |_, exp| Program::new(exp.clone(), s).exec()),
+            .try_fold(None, |_, exp| Program::new(exp.clone(), s).exec()),
         None => {
             let mut s = Scope::base();
             exprs
                 .iter()
-                .fold(None, |_, exp| Program::new(exp.clone(), &mut s).exec())
+                .try_fold(None, |_, exp| Program::new(exp.clone(), &mut s).exec())
         }
     }
 }
@@ -70,18 +309,25 @@ pub fn exec(exprs: Vec<AstNode>, scope: Option<&mut Scope>) -> Option<Result> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lang::{
+        ast,
+        test_util::eval,
+        token::{self, Span},
+    };
+    use std::thread;
 
     #[test]
     fn execs_basic_sexp() {
         assert_eq!(
             exec(
-                vec![AstNode::AST(vec![
-                    AstNode::Leaf("if".to_string()),
-                    AstNode::Leaf("#t".to_string()),
-                    AstNode::Leaf("1".to_string())
+                vec![AstNode::list(vec![
+                    AstNode::leaf("if"),
+                    AstNode::leaf("#t"),
+                    AstNode::leaf("1")
                 ])],
                 None
             )
+            .unwrap()
             .unwrap(),
             Result::Primitive(Primitive::I(1))
         )
@@ -91,18 +337,19 @@ mod tests {
     fn execs_nested_sexp() {
         assert_eq!(
             exec(
-                vec![AstNode::AST(vec![
-                    AstNode::Leaf("if".to_string()),
-                    AstNode::AST(vec![
-                        AstNode::Leaf("if".to_string()),
-                        AstNode::Leaf("1".to_string()),
-                        AstNode::Leaf("#f".to_string())
+                vec![AstNode::list(vec![
+                    AstNode::leaf("if"),
+                    AstNode::list(vec![
+                        AstNode::leaf("if"),
+                        AstNode::leaf("1"),
+                        AstNode::leaf("#f")
                     ]),
-                    AstNode::Leaf("1".to_string()),
-                    AstNode::Leaf("2".to_string())
+                    AstNode::leaf("1"),
+                    AstNode::leaf("2")
                 ])],
                 None
             )
+            .unwrap()
             .unwrap(),
             Result::Primitive(Primitive::I(2))
         )
@@ -113,17 +360,278 @@ mod tests {
         assert_eq!(
             exec(
                 vec![
-                    AstNode::AST(vec![
-                        AstNode::Leaf("define".to_string()),
-                        AstNode::Leaf("x".to_string()),
-                        AstNode::Leaf("1".to_string()),
+                    AstNode::list(vec![
+                        AstNode::leaf("define"),
+                        AstNode::leaf("x"),
+                        AstNode::leaf("1"),
                     ]),
-                    AstNode::Leaf("x".to_string())
+                    AstNode::leaf("x")
                 ],
                 None
             )
+            .unwrap()
             .unwrap(),
             Result::Primitive(Primitive::I(1))
         )
     }
+
+    #[test]
+    fn errors_on_unbound_symbol() {
+        assert_eq!(
+            exec(vec![AstNode::leaf("nope")], None),
+            Err(RiskError::Unbound(
+                "nope".to_string(),
+                Some(Span::default())
+            ))
+        )
+    }
+
+    #[test]
+    fn errors_on_call_to_primitive() {
+        assert!(matches!(
+            exec(vec![AstNode::list(vec![AstNode::leaf("1")])], None),
+            Err(RiskError::Type(..))
+        ))
+    }
+
+    #[test]
+    fn scope_survives_an_error() {
+        let mut s = Scope::base();
+        exec(
+            vec![AstNode::list(vec![
+                AstNode::leaf("define"),
+                AstNode::leaf("x"),
+                AstNode::leaf("1"),
+            ])],
+            Some(&mut s),
+        )
+        .unwrap();
+
+        assert!(exec(vec![AstNode::leaf("y")], Some(&mut s)).is_err());
+        assert_eq!(
+            exec(vec![AstNode::leaf("x")], Some(&mut s))
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::I(1))
+        )
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_expression() {
+        let err = exec(
+            ast::new(token::tokenize("(if #t\n  (not x))").unwrap()).unwrap(),
+            None,
+        )
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "<repl>:2:8: Unbound variable: x");
+    }
+
+    #[test]
+    fn builtin_errors_point_at_the_call() {
+        let err = eval("  (not)").unwrap_err();
+
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((1, 3)));
+    }
+
+    #[test]
+    fn functions_are_lexically_scoped() {
+        assert_eq!(
+            eval("(define x 1) (define (f) x) (define (g x) (f)) (g 2)")
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::I(1))
+        )
+    }
+
+    #[test]
+    fn function_bodies_dont_leak_definitions() {
+        assert!(matches!(
+            eval("(define (f) (define z 5)) (f) z"),
+            Err(RiskError::Unbound(..))
+        ))
+    }
+
+    #[test]
+    fn functions_see_later_definitions_in_their_environment() {
+        assert_eq!(
+            eval("(define (f) y) (define y #t) (f)").unwrap().unwrap(),
+            Result::Primitive(Primitive::B(true))
+        )
+    }
+
+    #[test]
+    fn lambdas_are_first_class() {
+        assert_eq!(
+            eval("((lambda (x) x) 3)").unwrap().unwrap(),
+            Result::Primitive(Primitive::I(3))
+        );
+        assert_eq!(
+            eval("(define (twice f x) (f (f x))) (twice (lambda (b) (not b)) #t)")
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::B(true))
+        );
+    }
+
+    #[test]
+    fn lambdas_close_over_their_environment() {
+        assert_eq!(
+            eval("(define (konst x) (lambda () x)) (define k (konst 7)) (define x 1) (k)")
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::I(7))
+        )
+    }
+
+    #[test]
+    fn strings_evaluate_to_themselves() {
+        assert_eq!(
+            eval(r#"(define greeting "hello world") (if (eq? greeting "hello world") "yes")"#)
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::S("yes".to_string()))
+        );
+    }
+
+    #[test]
+    fn apply_calls_functions_with_values() {
+        let mut s = Scope::base();
+        let int = |i| Result::Primitive(Primitive::I(i));
+
+        let plus = s.get("+").unwrap();
+        assert_eq!(
+            apply(plus, vec![int(1), int(2)], &mut s).unwrap().unwrap(),
+            int(3)
+        );
+
+        let f = eval("(lambda (x y) (- x y))").unwrap().unwrap();
+        assert_eq!(
+            apply(f, vec![int(5), int(2)], &mut s).unwrap().unwrap(),
+            int(3)
+        );
+
+        assert!(matches!(
+            apply(int(1), vec![], &mut s),
+            Err(RiskError::Type(..))
+        ));
+    }
+
+    #[test]
+    fn tail_calls_dont_grow_the_stack() {
+        let done = Result::Primitive(Primitive::Sym("done".to_string()));
+
+        assert_eq!(
+            eval("(define (count n) (if (= n 0) 'done (count (- n 1)))) (count 10000)")
+                .unwrap()
+                .unwrap(),
+            done
+        );
+        assert_eq!(
+            eval("(let loop ((i 0)) (if (< i 10000) (loop (+ i 1)) 'done))")
+                .unwrap()
+                .unwrap(),
+            done
+        );
+        assert_eq!(
+            eval(
+                "(define (ev? n) (cond ((= n 0) #t) (else (od? (- n 1)))))
+                 (define (od? n) (and (not (= n 0)) (ev? (- n 1))))
+                 (ev? 10000)"
+            )
+            .unwrap()
+            .unwrap(),
+            Result::Primitive(Primitive::B(true))
+        );
+        assert_eq!(
+            eval("(define (f n) (begin (when (= n 0) 'done) (let ((m (- n 1))) (if (< m 0) 'done (f m))))) (f 10000)")
+                .unwrap()
+                .unwrap(),
+            done
+        );
+    }
+
+    #[test]
+    fn deep_recursion_is_a_catchable_error() {
+        // Reaching the limit takes more stack than test threads have
+        let deep = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                let f = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))";
+                let within = eval(&format!("{} (f 5000)", f)).unwrap().unwrap();
+                let beyond = eval(&format!("{} (f {})", f, MAX_DEPTH)).unwrap_err();
+                let caught = eval(&format!("{} (guard (e (#t 'caught)) (f {}))", f, MAX_DEPTH))
+                    .unwrap()
+                    .unwrap();
+
+                (within.to_string(), beyond.to_string(), caught.to_string())
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(deep.0, "5000");
+        assert_eq!(
+            deep.1,
+            "<repl>:1:38: Runtime error: maximum recursion depth exceeded"
+        );
+        assert_eq!(deep.2, "caught");
+    }
+
+    #[test]
+    fn deeply_nested_code_and_data_are_catchable_errors() {
+        let deep = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                let read = eval(&"(".repeat(1_000_000)).unwrap_err();
+
+                // Data nests without limit, but not the code it's turned into
+                let nest = "(define (nest n acc) (if (= n 0) acc (nest (- n 1) (list acc))))";
+                let code = eval(&format!(
+                    "{} (eval (list 'quote (nest {} 0)))",
+                    nest, MAX_DEPTH
+                ))
+                .unwrap_err();
+
+                // A template nested half as deep as the limit can't be filled
+                // in from as deep again
+                let template = format!(
+                    "{}x{}",
+                    "(".repeat(MAX_DEPTH / 2),
+                    ")".repeat(MAX_DEPTH / 2)
+                );
+                let m = format!("(define-syntax m (syntax-rules () ((_ x) '{})))", template);
+                let f = "(define (f n) (if (= n 0) (m 0) (car (list (f (- n 1))))))";
+                let shallow = eval(&format!("{} {} (f 10)", m, f)).unwrap().unwrap();
+                let caught = eval(&format!(
+                    "{} {} (guard (e (#t 'caught)) (f {}))",
+                    m,
+                    f,
+                    MAX_DEPTH / 4
+                ))
+                .unwrap()
+                .unwrap();
+
+                (
+                    read.to_string(),
+                    code.to_string(),
+                    shallow.to_string().len(),
+                    caught.to_string(),
+                )
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            deep.0,
+            "<repl>:1:10001: Runtime error: maximum recursion depth exceeded"
+        );
+        assert_eq!(
+            deep.1,
+            "<repl>:1:72: Runtime error: maximum recursion depth exceeded"
+        );
+        assert_eq!(deep.2, MAX_DEPTH + 1);
+        assert_eq!(deep.3, "caught");
+    }
 }
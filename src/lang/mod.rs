@@ -2,5 +2,8 @@ pub mod ast;
 pub mod base;
 pub mod exec;
 pub mod scope;
+#[cfg(test)]
+pub mod test_util;
 pub mod token;
+pub mod trace;
 pub mod types;
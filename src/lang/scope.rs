@@ -1,63 +1,664 @@
+use crate::lang::ast::AstNode;
 use crate::lang::base;
-use crate::lang::types::builtin::Builtin;
-use crate::lang::types::primitive::Primitive;
+use crate::lang::exec::Tail;
+use crate::lang::types::builtin::{Builtin, BuiltinFn};
+use crate::lang::types::error::RiskResult;
+use crate::lang::types::pair::Pair;
 use crate::lang::types::result::Result;
+use crate::lang::types::syntax::Macro;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::{Rc, Weak};
 
+struct Frame {
+    map: HashMap<String, Result>,
+    parent: Option<Scope>,
+}
+
+// A handle to one frame of the environment chain. Cloning a Scope is cheap and
+// yields another handle to the same frame, so definitions made through either
+// are visible to both.
 #[derive(Clone)]
 pub struct Scope {
-    pub map: HashMap<String, Result>,
+    frame: Rc<RefCell<Frame>>,
 }
 
 impl Scope {
     pub fn base() -> Scope {
-        let mut base_scope: Scope = Scope {
-            map: HashMap::new(),
-        };
+        let mut base_scope: Scope = Scope::empty(None);
 
-        // bools
-        base_scope
-            .map
-            .insert(String::from("#t"), Result::Primitive(Primitive::B(true)));
+        // definition
+        base_scope.form("define", base::definition::definitiondef);
+        base_scope.form("lambda", base::definition::lambdadef);
+        base_scope.form("set!", base::definition::setbangdef);
+
+        // local bindings
+        base_scope.tail_form("let", base::binding::letdef);
+        base_scope.tail_form("let*", base::binding::letstardef);
+        base_scope.tail_form("letrec", base::binding::letrecdef);
+
+        // code as data
+        base_scope.form("quote", base::quote::quotedef);
+        base_scope.form("quasiquote", base::quote::quasiquotedef);
+        base_scope.form("unquote", base::quote::unquotedef);
+        base_scope.form("unquote-splicing", base::quote::unquotesplicingdef);
+
+        // macros
+        base_scope.form("defmacro", base::macros::defmacrodef);
+        base_scope.builtin("macroexpand", base::macros::macroexpanddef);
+        base_scope.builtin("macroexpand-1", base::macros::macroexpand1def);
+        base_scope.form("define-syntax", base::macros::definesyntaxdef);
+        base_scope.form("syntax-rules", base::macros::syntaxrulesdef);
+
+        // logic functions
+        base_scope.tail_form("if", base::logic::ifdef);
+        base_scope.tail_form("begin", base::logic::begindef);
+        base_scope.builtin("eq?", base::logic::eqhuhdef);
+        base_scope.builtin("not", base::logic::notdef);
+        base_scope.tail_form("and", base::logic::anddef);
+        base_scope.tail_form("or", base::logic::ordef);
+        base_scope.tail_form("cond", base::logic::conddef);
+        base_scope.tail_form("case", base::logic::casedef);
+        base_scope.tail_form("when", base::logic::whendef);
+        base_scope.tail_form("unless", base::logic::unlessdef);
+
+        // control
+        base_scope.tail_builtin("apply", base::control::applydef);
+        base_scope.tail_builtin("eval", base::control::evaldef);
+        base_scope.builtin("call/cc", base::control::callccdef);
+        base_scope.builtin("call-with-current-continuation", base::control::callccdef);
+        base_scope.builtin("current-environment", base::control::currentenvironmentdef);
+        base_scope.builtin("base-environment", base::control::baseenvironmentdef);
+        base_scope.builtin("make-environment", base::control::makeenvironmentdef);
+        base_scope.builtin("backtrace", base::control::backtracedef);
+
+        // exceptions
+        base_scope.builtin("error", base::exception::errordef);
+        base_scope.builtin("raise", base::exception::raisedef);
+        base_scope.tail_form("guard", base::exception::guarddef);
+        base_scope.builtin("dynamic-wind", base::exception::dynamicwinddef);
+        base_scope.builtin("error?", base::exception::errorhuhdef);
+        base_scope.builtin("error-object?", base::exception::errorhuhdef);
+        base_scope.builtin(
+            "error-object-message",
+            base::exception::errorobjectmessagedef,
+        );
+        base_scope.builtin(
+            "error-object-irritants",
+            base::exception::errorobjectirritantsdef,
+        );
+
+        // arithmetic
+        base_scope.builtin("+", base::math::adddef);
+        base_scope.builtin("-", base::math::subdef);
+        base_scope.builtin("*", base::math::muldef);
+        base_scope.builtin("/", base::math::divdef);
+        base_scope.builtin("quotient", base::math::quotientdef);
+        base_scope.builtin("remainder", base::math::remainderdef);
+        base_scope.builtin("modulo", base::math::modulodef);
+
+        // numeric comparison
+        base_scope.builtin("=", base::math::numeqdef);
+        base_scope.builtin("<", base::math::ltdef);
+        base_scope.builtin(">", base::math::gtdef);
+        base_scope.builtin("<=", base::math::ltedef);
+        base_scope.builtin(">=", base::math::gtedef);
+        base_scope.builtin("zero?", base::math::zerohuhdef);
+        base_scope.builtin("positive?", base::math::positivehuhdef);
+        base_scope.builtin("negative?", base::math::negativehuhdef);
+        base_scope.builtin("even?", base::math::evenhuhdef);
+        base_scope.builtin("odd?", base::math::oddhuhdef);
+        base_scope.builtin("min", base::math::mindef);
+        base_scope.builtin("max", base::math::maxdef);
+
+        // lists
+        base_scope.builtin("cons", base::list::consdef);
+        base_scope.builtin("car", base::list::cardef);
+        base_scope.builtin("cdr", base::list::cdrdef);
+        base_scope.builtin("list", base::list::listdef);
+        base_scope.builtin("null?", base::list::nullhuhdef);
+        base_scope.builtin("pair?", base::list::pairhuhdef);
+        base_scope.builtin("length", base::list::lengthdef);
+        base_scope.builtin("append", base::list::appenddef);
+        base_scope.builtin("reverse", base::list::reversedef);
 
         base_scope
-            .map
-            .insert(String::from("#f"), Result::Primitive(Primitive::B(false)));
+    }
 
-        // definition
-        base_scope.map.insert(
-            String::from("define"),
+    // A new, empty frame whose lookups fall back to self
+    pub fn child(&self) -> Scope {
+        Scope::empty(Some(self.clone()))
+    }
+
+    // A frame with no bindings at all, not even builtins, unless it has a parent
+    pub fn empty(parent: Option<Scope>) -> Scope {
+        let frame = Rc::new(RefCell::new(Frame {
+            map: HashMap::new(),
+            parent,
+        }));
+
+        let full = FRAMES.with(|frames| {
+            let mut frames = frames.borrow_mut();
+            frames.all.push(Rc::downgrade(&frame));
+            frames.all.len() >= frames.threshold
+        });
+        if full {
+            collect();
+        }
+
+        Scope { frame }
+    }
+
+    // Look a name up, walking outwards through enclosing frames. A name renamed
+    // by a macro expansion that the expansion didn't bind refers to whatever the
+    // name it was made from does where the macro was defined
+    pub fn get(&self, name: &str) -> Option<Result> {
+        self.lookup(name).or_else(|| {
+            let (original, env) = renamed_from(name)?;
+            match env {
+                Some(env) => env.get(original),
+                None => self.get(original),
+            }
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Option<Result> {
+        let frame = self.frame.borrow();
+
+        match frame.map.get(name) {
+            Some(r) => Some(r.clone()),
+            None => frame.parent.as_ref().and_then(|p| p.lookup(name)),
+        }
+    }
+
+    // Bind a name in this frame, shadowing any outer binding
+    pub fn define(&mut self, name: &str, value: Result) {
+        self.frame.borrow_mut().map.insert(name.to_string(), value);
+    }
+
+    // Rebind the nearest existing binding of name, returning false if there
+    // isn't one anywhere in the chain. Renamed names fall back as in get
+    pub fn set(&mut self, name: &str, value: Result) -> bool {
+        if self.lookup(name).is_none() {
+            return match renamed_from(name) {
+                Some((original, Some(mut env))) => env.set(original, value),
+                Some((original, None)) => self.set(original, value),
+                None => false,
+            };
+        }
+
+        self.assign(name, value)
+    }
+
+    fn assign(&mut self, name: &str, value: Result) -> bool {
+        let mut frame = self.frame.borrow_mut();
+
+        match frame.map.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => match frame.parent.as_mut() {
+                Some(parent) => parent.assign(name, value),
+                None => false,
+            },
+        }
+    }
+
+    fn builtin(
+        &mut self,
+        name: &str,
+        f: fn(Vec<AstNode>, &mut Scope) -> RiskResult<Option<Result>>,
+    ) {
+        self.register(name, BuiltinFn::Value(f), false);
+    }
+
+    // Like builtin, for forms that can hand their last expression back to the
+    // evaluator to run in tail position
+    fn tail_builtin(&mut self, name: &str, f: fn(Vec<AstNode>, &mut Scope) -> RiskResult<Tail>) {
+        self.register(name, BuiltinFn::Tail(f), false);
+    }
+
+    // Like builtin, for special forms that use their arguments as code
+    fn form(&mut self, name: &str, f: fn(Vec<AstNode>, &mut Scope) -> RiskResult<Option<Result>>) {
+        self.register(name, BuiltinFn::Value(f), true);
+    }
+
+    fn tail_form(&mut self, name: &str, f: fn(Vec<AstNode>, &mut Scope) -> RiskResult<Tail>) {
+        self.register(name, BuiltinFn::Tail(f), true);
+    }
+
+    fn register(&mut self, name: &str, f: BuiltinFn, form: bool) {
+        self.define(
+            name,
             Result::Builtin(Builtin {
-                id: "define".to_string(),
-                f: base::definition::definitiondef,
+                id: name.to_string(),
+                f,
+                form,
             }),
         );
+    }
+}
 
-        // logic functions
-        base_scope.map.insert(
-            String::from("if"),
-            Result::Builtin(Builtin {
-                id: "if".to_string(),
-                f: base::logic::ifdef,
-            }),
+// Macro expansions rename the symbols they introduce by adding a space, the
+// number of the expansion and which environment the macro was defined in.
+// Names written in source can't contain a space, so a renamed name never clashes
+// with one from the code using the macro
+pub fn rename(name: &str, expansion: usize, env: &Scope) -> String {
+    format!("{} {}@{}", name, expansion, env.macro_env_id())
+}
+
+// The name a renamed name was made from, undoing one rename
+pub fn unrenamed(name: &str) -> Option<&str> {
+    let (original, suffix) = name.rsplit_once(' ')?;
+    let (expansion, env) = suffix.split_once('@')?;
+    let is_number = |n: &str| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit());
+
+    match !original.is_empty() && is_number(expansion) && is_number(env) {
+        true => Some(original),
+        false => None,
+    }
+}
+
+// Like unrenamed, also giving the environment of the macro that renamed it,
+// unless that's gone
+fn renamed_from(name: &str) -> Option<(&str, Option<Scope>)> {
+    let original = unrenamed(name)?;
+    let id = name.rsplit_once('@')?.1.parse::<usize>().ok()?;
+    let env = MACRO_ENVS.with(|envs| envs.borrow().frames.get(id).and_then(Weak::upgrade));
+
+    Some((original, env.map(|frame| Scope { frame })))
+}
+
+// Environments macros were defined in, numbered so renamed names can refer to
+// them. Held weakly, so numbering a frame doesn't keep it alive
+#[derive(Default)]
+struct MacroEnvs {
+    ids: HashMap<*const RefCell<Frame>, usize>,
+    frames: Vec<Weak<RefCell<Frame>>>,
+}
+
+thread_local! {
+    static MACRO_ENVS: RefCell<MacroEnvs> = RefCell::new(MacroEnvs::default());
+}
+
+impl Scope {
+    fn macro_env_id(&self) -> usize {
+        MACRO_ENVS.with(|envs| {
+            let mut envs = envs.borrow_mut();
+            let ptr = Rc::as_ptr(&self.frame);
+
+            // A frame that has been freed may have had its address reused
+            if let Some(&id) = envs.ids.get(&ptr)
+                && envs.frames[id].ptr_eq(&Rc::downgrade(&self.frame))
+            {
+                return id;
+            }
+
+            let id = envs.frames.len();
+            envs.frames.push(Rc::downgrade(&self.frame));
+            envs.ids.insert(ptr, id);
+            id
+        })
+    }
+}
+
+// The name as it was written in source, undoing every rename
+pub fn original_name(name: &str) -> &str {
+    match unrenamed(name) {
+        Some(original) => original_name(original),
+        None => name,
+    }
+}
+
+// Closures hold their environment, and are usually bound in it or in a frame
+// under it, so frames form cycles that reference counting alone never frees.
+// Every frame is tracked here so the cycles can be found and broken
+struct Frames {
+    all: Vec<Weak<RefCell<Frame>>>,
+    // Collect once this many frames are tracked
+    threshold: usize,
+}
+
+const MIN_THRESHOLD: usize = 1024;
+
+thread_local! {
+    static FRAMES: RefCell<Frames> = const {
+        RefCell::new(Frames {
+            all: Vec::new(),
+            threshold: MIN_THRESHOLD,
+        })
+    };
+}
+
+// Something a value holds a counted reference to
+enum Edge<'a> {
+    Frame(&'a Rc<RefCell<Frame>>),
+    Pair(&'a Rc<Pair>),
+}
+
+// The frames and pairs a value references directly. Pairs are followed
+// separately, as they can be shared
+fn edges<'a>(value: &'a Result, out: &mut Vec<Edge<'a>>) {
+    match value {
+        Result::FnDef(f) | Result::Macro(Macro::Function(f)) => out.push(Edge::Frame(&f.env.frame)),
+        Result::Macro(Macro::Rules(rules)) => out.push(Edge::Frame(&rules.env.frame)),
+        Result::Environment(scope) => out.push(Edge::Frame(&scope.frame)),
+        Result::Condition(condition) => {
+            for irritant in condition.irritants.iter() {
+                edges(irritant, out);
+            }
+        }
+        Result::Pair(pair) => out.push(Edge::Pair(pair)),
+        Result::Primitive(_) | Result::Builtin(_) | Result::Continuation(_) | Result::Nil => (),
+    }
+}
+
+// Frees frames that can't be reached any more. Frames and the pairs in them
+// are nodes, and any reference to a node that doesn't come from another node
+// must come from the interpreter itself, making that node live. Everything
+// reachable from a live node is kept, and the rest have their bindings cleared,
+// which breaks the cycles keeping them around. Pairs can't form cycles by
+// themselves, so clearing frames is enough
+pub fn collect() {
+    let frames: Vec<Rc<RefCell<Frame>>> = FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        frames.all.retain(|frame| frame.strong_count() > 0);
+        frames.all.iter().filter_map(Weak::upgrade).collect()
+    });
+    let index: HashMap<*const RefCell<Frame>, usize> = frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| (Rc::as_ptr(frame), i))
+        .collect();
+
+    // Frames being changed are in use, so leave collecting until later
+    let borrowed = match frames
+        .iter()
+        .map(|frame| frame.try_borrow())
+        .collect::<std::result::Result<Vec<_>, _>>()
+    {
+        Ok(borrowed) => borrowed,
+        Err(_) => return,
+    };
+
+    // Nodes are the frames followed by the pairs found in them, each with the
+    // nodes it references
+    let mut pairs: Vec<Rc<Pair>> = Vec::new();
+    let mut pair_index: HashMap<*const Pair, usize> = HashMap::new();
+    let mut children: Vec<Vec<usize>> = Vec::new();
+
+    let mut node_of = |edge: Edge, pairs: &mut Vec<Rc<Pair>>| match edge {
+        Edge::Frame(frame) => index.get(&Rc::as_ptr(frame)).copied(),
+        Edge::Pair(pair) => Some(*pair_index.entry(Rc::as_ptr(pair)).or_insert_with(|| {
+            pairs.push(pair.clone());
+            frames.len() + pairs.len() - 1
+        })),
+    };
+
+    for frame in borrowed.iter() {
+        let mut out = Vec::new();
+        if let Some(parent) = &frame.parent {
+            out.push(Edge::Frame(&parent.frame));
+        }
+        for value in frame.map.values() {
+            edges(value, &mut out);
+        }
+        children.push(
+            out.into_iter()
+                .filter_map(|e| node_of(e, &mut pairs))
+                .collect(),
         );
+    }
 
-        base_scope.map.insert(
-            String::from("eq?"),
-            Result::Builtin(Builtin {
-                id: "eq?".to_string(),
-                f: base::logic::eqhuhdef,
-            }),
+    let mut next = 0;
+    while next < pairs.len() {
+        let pair = pairs[next].clone();
+        let mut out = Vec::new();
+        edges(&pair.car, &mut out);
+        edges(&pair.cdr, &mut out);
+        children.push(
+            out.into_iter()
+                .filter_map(|e| node_of(e, &mut pairs))
+                .collect(),
         );
+        next += 1;
+    }
+    drop(borrowed);
 
-        base_scope.map.insert(
-            String::from("not"),
-            Result::Builtin(Builtin {
-                id: "not".to_string(),
-                f: base::logic::notdef,
-            }),
+    // Each node is held once by the lists above
+    let mut outside: Vec<usize> = frames
+        .iter()
+        .map(Rc::strong_count)
+        .chain(pairs.iter().map(Rc::strong_count))
+        .map(|count| count - 1)
+        .collect();
+    for child in children.iter().flatten() {
+        outside[*child] -= 1;
+    }
+
+    let mut live = vec![false; outside.len()];
+    let mut pending: Vec<usize> = (0..outside.len()).filter(|&i| outside[i] > 0).collect();
+    while let Some(node) = pending.pop() {
+        if !mem::replace(&mut live[node], true) {
+            pending.extend(children[node].iter().copied());
+        }
+    }
+
+    let mut garbage = Vec::new();
+    for (frame, _) in frames.iter().zip(live.iter()).filter(|(_, live)| !**live) {
+        if let Ok(mut frame) = frame.try_borrow_mut() {
+            garbage.push((mem::take(&mut frame.map), frame.parent.take()));
+        }
+    }
+    let scanned = frames.len() - garbage.len() + pairs.len();
+    drop(garbage);
+    drop(pairs);
+    drop(frames);
+
+    FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        frames.all.retain(|frame| frame.strong_count() > 0);
+        frames.threshold = MIN_THRESHOLD.max(2 * scanned);
+    });
+}
+
+// Scopes are compared by identity, two handles are equal when they point at the
+// same frame
+impl PartialEq for Scope {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.frame, &other.frame)
+    }
+}
+
+// Frames can (and with closures usually do) contain themselves, so don't try to
+// print their contents
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scope").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{
+        ast, exec, token,
+        types::{pair, primitive::Primitive, userfunc::FnDef},
+    };
+
+    #[test]
+    fn child_sees_parent_bindings() {
+        let mut parent = Scope::base();
+        parent.define("x", Result::Primitive(Primitive::I(1)));
+
+        assert_eq!(
+            parent.child().get("x"),
+            Some(Result::Primitive(Primitive::I(1)))
         );
+    }
 
-        base_scope
+    #[test]
+    fn child_bindings_shadow_without_leaking() {
+        let mut parent = Scope::base();
+        parent.define("x", Result::Primitive(Primitive::I(1)));
+
+        let mut child = parent.child();
+        child.define("x", Result::Primitive(Primitive::I(2)));
+        child.define("y", Result::Primitive(Primitive::I(3)));
+
+        assert_eq!(child.get("x"), Some(Result::Primitive(Primitive::I(2))));
+        assert_eq!(parent.get("x"), Some(Result::Primitive(Primitive::I(1))));
+        assert_eq!(parent.get("y"), None);
+    }
+
+    #[test]
+    fn clones_share_a_frame() {
+        let s = Scope::base();
+        let mut handle = s.clone();
+        handle.define("x", Result::Primitive(Primitive::B(true)));
+
+        assert_eq!(s.get("x"), Some(Result::Primitive(Primitive::B(true))));
+        assert!(s == handle);
+        assert!(s != s.child());
+    }
+
+    #[test]
+    fn set_updates_nearest_binding() {
+        let mut parent = Scope::base();
+        parent.define("x", Result::Primitive(Primitive::I(1)));
+        let mut child = parent.child();
+
+        assert!(child.set("x", Result::Primitive(Primitive::I(2))));
+        assert_eq!(parent.get("x"), Some(Result::Primitive(Primitive::I(2))));
+        assert_eq!(child.get("x"), Some(Result::Primitive(Primitive::I(2))));
+    }
+
+    #[test]
+    fn set_does_not_create_bindings() {
+        let mut s = Scope::base().child();
+
+        assert!(!s.set("x", Result::Primitive(Primitive::I(1))));
+        assert_eq!(s.get("x"), None);
+    }
+
+    #[test]
+    fn renamed_names_fall_back_to_the_original() {
+        let mut scope = Scope::base();
+        scope.define("x", Result::Primitive(Primitive::I(1)));
+
+        let renamed = rename(&rename("x", 1, &scope), 2, &scope);
+        assert_eq!(original_name(&renamed), "x");
+        assert_eq!(
+            scope.get(&renamed),
+            Some(Result::Primitive(Primitive::I(1)))
+        );
+
+        scope.define(&rename("x", 1, &scope), Result::Primitive(Primitive::I(2)));
+        assert_eq!(
+            scope.get(&renamed),
+            Some(Result::Primitive(Primitive::I(2)))
+        );
+        assert_eq!(scope.get("x"), Some(Result::Primitive(Primitive::I(1))));
+
+        assert!(scope.set(&rename("x", 3, &scope), Result::Primitive(Primitive::I(4))));
+        assert_eq!(scope.get("x"), Some(Result::Primitive(Primitive::I(4))));
+
+        assert_eq!(unrenamed(" arg0"), None);
+        assert_eq!(unrenamed("x 1"), None);
+        assert_eq!(unrenamed("x"), None);
+    }
+
+    #[test]
+    fn renamed_names_fall_back_where_the_macro_was_defined() {
+        let mut definition = Scope::base();
+        definition.define("x", Result::Primitive(Primitive::I(1)));
+        let mut usage = Scope::base();
+        usage.define("x", Result::Primitive(Primitive::I(2)));
+
+        let renamed = rename("x", 1, &definition);
+        assert_eq!(
+            usage.get(&renamed),
+            Some(Result::Primitive(Primitive::I(1)))
+        );
+
+        assert!(usage.set(&renamed, Result::Primitive(Primitive::I(3))));
+        assert_eq!(
+            definition.get("x"),
+            Some(Result::Primitive(Primitive::I(3)))
+        );
+        assert_eq!(usage.get("x"), Some(Result::Primitive(Primitive::I(2))));
+    }
+
+    fn closure_in(scope: &Scope) -> Result {
+        Result::FnDef(FnDef::new(vec![], vec![AstNode::leaf("1")], scope.clone()))
+    }
+
+    #[test]
+    fn collect_frees_frames_holding_their_own_closures() {
+        let base = Scope::base();
+        let mut local = base.child();
+        local.define("f", closure_in(&local));
+        local.define("fs", pair::list(vec![closure_in(&local)]));
+
+        let frame = Rc::downgrade(&local.frame);
+        drop(local);
+        assert!(frame.upgrade().is_some());
+
+        collect();
+        assert!(frame.upgrade().is_none());
+        assert!(base.get("car").is_some());
+    }
+
+    #[test]
+    fn collect_keeps_frames_that_are_still_reachable() {
+        let mut base = Scope::base();
+        let mut local = base.child();
+        local.define("x", Result::Primitive(Primitive::I(1)));
+        local.define("f", closure_in(&local));
+        let inner = local.child();
+
+        // Reachable through a binding, a list held outside any frame, and a
+        // child frame
+        base.define("g", closure_in(&local));
+        let held = pair::list(vec![closure_in(&local)]);
+        let frame = Rc::downgrade(&local.frame);
+        drop(local);
+
+        collect();
+        assert!(frame.upgrade().is_some());
+        assert_eq!(inner.get("x"), Some(Result::Primitive(Primitive::I(1))));
+
+        base.define("g", Result::Nil);
+        collect();
+        assert!(frame.upgrade().is_some());
+
+        drop(inner);
+        drop(held);
+        collect();
+        assert!(frame.upgrade().is_none());
+    }
+
+    #[test]
+    fn calls_making_local_closures_dont_grow_the_heap() {
+        let mut scope = Scope::base();
+        exec::exec(
+            ast::new(
+                token::tokenize(
+                    "(define (f n) (define (g) n) (let loop ((i 0)) (if (< i 3) (loop (+ i 1)) (g))))
+                     (let loop ((i 0)) (if (< i 5000) (begin (f i) (loop (+ i 1)))))",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+            Some(&mut scope),
+        )
+        .unwrap();
+
+        collect();
+        let tracked = FRAMES.with(|frames| frames.borrow().all.len());
+        assert!(tracked < 100, "{} frames still tracked", tracked);
     }
 }
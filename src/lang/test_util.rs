@@ -0,0 +1,17 @@
+use crate::lang::{
+    ast, exec, token,
+    types::{error::RiskResult, result::Result},
+};
+
+// Runs source in a fresh base scope, returning the last form's value
+pub fn eval(source: &str) -> RiskResult<Option<Result>> {
+    exec::exec(ast::new(token::tokenize(source)?)?, None)
+}
+
+// The printed value of source, or "None" if it has none
+pub fn shows(source: &str) -> String {
+    match eval(source).unwrap() {
+        Some(r) => r.to_string(),
+        None => "None".to_string(),
+    }
+}
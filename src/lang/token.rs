@@ -1,27 +1,236 @@
-pub fn tokenize(line: &str) -> Vec<String> {
-    line.replace("(", " ( ")
-        .replace(")", " ) ")
-        .replace("\n", " ")
-        .replace("\t", " ")
-        .split(" ")
-        .map(|s| s.to_string())
-        .filter(|s| !s.eq(""))
-        .collect::<Vec<_>>()
+use std::{fmt::Display, iter::Peekable, rc::Rc, str::CharIndices};
+
+use crate::lang::types::error::{RiskError, RiskResult};
+
+// Location of a piece of source. Lines and columns start at 1, the byte range
+// is a half open range into the original source.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub col: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // Span covering everything from the start of self to the end of other
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: self.line,
+            col: self.col,
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+impl Default for Span {
+    fn default() -> Span {
+        Span {
+            file: Rc::from(""),
+            line: 0,
+            col: 0,
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TokenKind {
+    Open,
+    Close,
+    Atom,
+    // String literal, the token text is the contents with escapes applied
+    Str,
+    // ' reader shorthand for (quote ...)
+    Quote,
+    // ` reader shorthand for (quasiquote ...)
+    Quasiquote,
+    // , reader shorthand for (unquote ...)
+    Unquote,
+    // ,@ reader shorthand for (unquote-splicing ...)
+    UnquoteSplicing,
+    // #:name, used to pass arguments by name. The token text includes the #:
+    Keyword,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    file: Rc<str>,
+    line: usize,
+    col: usize,
+}
+
+impl Cursor<'_> {
+    // Next char along with its byte offset and the position it starts at
+    fn next(&mut self) -> Option<(usize, char, Span)> {
+        let (i, ch) = self.chars.next()?;
+        let at = Span {
+            file: self.file.clone(),
+            line: self.line,
+            col: self.col,
+            start: i,
+            end: i + ch.len_utf8(),
+        };
+
+        if ch == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+
+        Some((i, ch, at))
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, ch)| *ch)
+    }
+}
+
+fn is_delimiter(ch: char) -> bool {
+    ch.is_whitespace() || ch == '(' || ch == ')' || ch == '"' || ch == '`' || ch == ','
+}
+
+// Reads the rest of a string literal after its opening quote
+fn string_literal(cursor: &mut Cursor, opening: &Span) -> RiskResult<(String, Span)> {
+    let mut contents = String::new();
+
+    loop {
+        match cursor.next() {
+            None => {
+                return Err(RiskError::Syntax(
+                    "unterminated string literal".to_string(),
+                    Some(opening.clone()),
+                ));
+            }
+            Some((_, '"', at)) => return Ok((contents, opening.to(&at))),
+            Some((_, '\\', at)) => contents.push(escape(cursor, &at)?),
+            Some((_, ch, _)) => contents.push(ch),
+        }
+    }
+}
+
+// Reads the escape sequence following a backslash
+fn escape(cursor: &mut Cursor, backslash: &Span) -> RiskResult<char> {
+    let invalid = |msg: &str| RiskError::Syntax(msg.to_string(), Some(backslash.clone()));
+
+    match cursor.next() {
+        Some((_, 'n', _)) => Ok('\n'),
+        Some((_, 't', _)) => Ok('\t'),
+        Some((_, '"', _)) => Ok('"'),
+        Some((_, '\\', _)) => Ok('\\'),
+        Some((_, 'u', _)) => {
+            if cursor.peek() != Some('{') {
+                return Err(invalid("expected '{' after \\u"));
+            }
+            cursor.next();
+
+            let mut hex = String::new();
+            loop {
+                match cursor.next() {
+                    Some((_, '}', _)) => break,
+                    Some((_, ch, _)) if ch.is_ascii_hexdigit() && hex.len() < 6 => hex.push(ch),
+                    _ => return Err(invalid("malformed \\u{...} escape")),
+                }
+            }
+
+            u32::from_str_radix(&hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| invalid("invalid unicode code point in \\u{...} escape"))
+        }
+        Some((_, ch, _)) => Err(invalid(&format!("unknown escape sequence \\{}", ch))),
+        None => Err(invalid("unterminated string literal")),
+    }
+}
+
+pub fn tokenize(line: &str) -> RiskResult<Vec<Token>> {
+    tokenize_file(line, "<repl>")
+}
+
+pub fn tokenize_file(source: &str, file: &str) -> RiskResult<Vec<Token>> {
+    let mut cursor = Cursor {
+        chars: source.char_indices().peekable(),
+        file: Rc::from(file),
+        line: 1,
+        col: 1,
+    };
+    let mut tokens = Vec::new();
+
+    while let Some((i, ch, at)) = cursor.next() {
+        let (kind, text, span) = match ch {
+            c if c.is_whitespace() => continue,
+            '(' => (TokenKind::Open, ch.to_string(), at),
+            ')' => (TokenKind::Close, ch.to_string(), at),
+            '\'' => (TokenKind::Quote, ch.to_string(), at),
+            '`' => (TokenKind::Quasiquote, ch.to_string(), at),
+            ',' => match cursor.peek() {
+                Some('@') => {
+                    let end = cursor.next().expect("peeked a char").2;
+                    (TokenKind::UnquoteSplicing, ",@".to_string(), at.to(&end))
+                }
+                _ => (TokenKind::Unquote, ch.to_string(), at),
+            },
+            '"' => {
+                let (contents, span) = string_literal(&mut cursor, &at)?;
+                (TokenKind::Str, contents, span)
+            }
+            _ => {
+                let mut end = at.clone();
+                while cursor.peek().is_some_and(|c| !is_delimiter(c)) {
+                    end = cursor.next().expect("peeked a char").2;
+                }
+
+                let text = &source[i..end.end];
+                let kind = match text.strip_prefix("#:") {
+                    Some(name) if !name.is_empty() => TokenKind::Keyword,
+                    _ => TokenKind::Atom,
+                };
+
+                (kind, text.to_string(), at.to(&end))
+            }
+        };
+
+        tokens.push(Token { kind, text, span });
+    }
+
+    Ok(tokens)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn texts(tokens: RiskResult<Vec<Token>>) -> Vec<String> {
+        tokens.unwrap().into_iter().map(|t| t.text).collect()
+    }
+
     #[test]
     fn tokenize_single_token() {
-        assert_eq!(tokenize("token"), ["token"]);
+        assert_eq!(texts(tokenize("token")), ["token"]);
     }
 
     #[test]
     fn tokenize_list_of_tokens() {
         assert_eq!(
-            tokenize("(1 2 3)) 1 token"),
+            texts(tokenize("(1 2 3)) 1 token")),
             ["(", "1", "2", "3", ")", ")", "1", "token"]
         )
     }
@@ -29,7 +238,7 @@ mod tests {
     #[test]
     fn tokenize_strips_excess_whitespace() {
         assert_eq!(
-            tokenize("( 1 2    3) ) () ) 1 token"),
+            texts(tokenize("( 1 2    3) ) () ) 1 token")),
             ["(", "1", "2", "3", ")", ")", "(", ")", ")", "1", "token"]
         )
     }
@@ -37,10 +246,10 @@ mod tests {
     #[test]
     fn tokenize_handles_newline_and_tabs() {
         assert_eq!(
-            tokenize(
+            texts(tokenize(
                 "( 1 2 3
                 )"
-            ),
+            )),
             ["(", "1", "2", "3", ")"]
         );
     }
@@ -48,8 +257,113 @@ mod tests {
     #[test]
     fn tokenize_handles_touching_sexps() {
         assert_eq!(
-            tokenize("(1 2 3)(1)"),
+            texts(tokenize("(1 2 3)(1)")),
             ["(", "1", "2", "3", ")", "(", "1", ")"]
         );
     }
+
+    #[test]
+    fn tokenize_records_spans() {
+        let tokens = tokenize_file("(define x\n  10)", "main.risk").unwrap();
+
+        assert_eq!(
+            tokens[2].span,
+            Span {
+                file: Rc::from("main.risk"),
+                line: 1,
+                col: 9,
+                start: 8,
+                end: 9,
+            }
+        );
+        assert_eq!(
+            tokens[3].span,
+            Span {
+                file: Rc::from("main.risk"),
+                line: 2,
+                col: 3,
+                start: 12,
+                end: 14,
+            }
+        );
+        assert_eq!(tokens[4].span.to_string(), "main.risk:2:5");
+    }
+
+    #[test]
+    fn tokenize_string_literals_keep_spaces_and_parens() {
+        let tokens = tokenize("(f \"hello (world)\" x)").unwrap();
+
+        assert_eq!(tokens[2].kind, TokenKind::Str);
+        assert_eq!(tokens[2].text, "hello (world)");
+        assert_eq!((tokens[2].span.start, tokens[2].span.end), (3, 18));
+        assert_eq!(tokens[3].text, "x");
+    }
+
+    #[test]
+    fn tokenize_string_escapes() {
+        assert_eq!(
+            texts(tokenize(r#""a\nb\tc\"d\\e\u{1F600}\u{e9}""#)),
+            ["a\nb\tc\"d\\e\u{1F600}\u{e9}"]
+        );
+    }
+
+    #[test]
+    fn tokenize_strings_delimit_atoms() {
+        assert_eq!(texts(tokenize(r#"a"b"c"#)), ["a", "b", "c"]);
+        assert_eq!(texts(tokenize(r#""""#)), [""]);
+    }
+
+    #[test]
+    fn tokenize_rejects_bad_strings() {
+        assert!(matches!(
+            tokenize("\"open"),
+            Err(RiskError::Syntax(_, Some(Span { col: 1, .. })))
+        ));
+        assert!(matches!(tokenize(r#""\q""#), Err(RiskError::Syntax(..))));
+        assert!(matches!(
+            tokenize(r#""\u{110000}""#),
+            Err(RiskError::Syntax(..))
+        ));
+        assert!(matches!(
+            tokenize(r#""\u1234""#),
+            Err(RiskError::Syntax(..))
+        ));
+    }
+
+    #[test]
+    fn tokenize_quote_shorthand() {
+        let tokens = tokenize("'(a 'b) don't").unwrap();
+
+        assert_eq!(
+            tokens.iter().map(|t| t.text.as_str()).collect::<Vec<_>>(),
+            ["'", "(", "a", "'", "b", ")", "don't"]
+        );
+        assert_eq!(tokens[0].kind, TokenKind::Quote);
+        assert_eq!(tokens[3].kind, TokenKind::Quote);
+        assert_eq!(tokens[6].kind, TokenKind::Atom);
+    }
+
+    #[test]
+    fn tokenize_quasiquote_shorthand() {
+        let tokens = tokenize("`(a ,b ,@c)d,e").unwrap();
+
+        assert_eq!(
+            tokens.iter().map(|t| t.text.as_str()).collect::<Vec<_>>(),
+            ["`", "(", "a", ",", "b", ",@", "c", ")", "d", ",", "e"]
+        );
+        assert_eq!(tokens[0].kind, TokenKind::Quasiquote);
+        assert_eq!(tokens[3].kind, TokenKind::Unquote);
+        assert_eq!(tokens[5].kind, TokenKind::UnquoteSplicing);
+        assert_eq!((tokens[5].span.start, tokens[5].span.end), (7, 9));
+    }
+
+    #[test]
+    fn tokenize_keywords() {
+        let tokens = tokenize("(f #:timeout 30 #: #t)").unwrap();
+
+        assert_eq!(tokens[2].kind, TokenKind::Keyword);
+        assert_eq!(tokens[2].text, "#:timeout");
+        assert_eq!(tokens[4].kind, TokenKind::Atom);
+        assert_eq!(tokens[5].kind, TokenKind::Atom);
+    }
 }
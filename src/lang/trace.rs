@@ -0,0 +1,237 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::lang::{
+    ast::AstNode,
+    base::quote,
+    token::Span,
+    types::{builtin::Builtin, pair, primitive::Primitive, result::Result, userfunc::FnDef},
+};
+
+// What a call was made with
+#[derive(Debug, Clone)]
+pub enum Args {
+    // Arguments to a function, already evaluated
+    Values(Vec<Result>),
+    // Builtins evaluate their own arguments, so only the forms are known. Holds
+    // the whole call, shared with the code it's in
+    Forms(Rc<[AstNode]>),
+}
+
+// A call that hasn't returned yet
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    // Where the call was made, missing for functions called by builtins like
+    // apply, dynamic-wind and call/cc rather than from source
+    pub span: Option<Span>,
+    pub args: Args,
+}
+
+impl Frame {
+    pub fn function(
+        f: &FnDef,
+        span: Option<&Span>,
+        args: &[Result],
+        keywords: &[(String, Result)],
+    ) -> Frame {
+        let mut values = args.to_vec();
+        for (k, value) in keywords {
+            values.push(Result::Primitive(Primitive::Kw(k.to_string())));
+            values.push(value.clone());
+        }
+
+        Frame {
+            name: f.name.clone().unwrap_or_else(|| "lambda".to_string()),
+            span: span.cloned(),
+            args: Args::Values(values),
+        }
+    }
+
+    pub fn builtin(b: &Builtin, span: Option<&Span>, args: Args) -> Frame {
+        Frame {
+            name: b.id.clone(),
+            span: span.cloned(),
+            args,
+        }
+    }
+
+    // The call as a list, with the function's name first
+    pub fn to_list(&self) -> Result {
+        let mut items = vec![Result::Primitive(Primitive::Sym(self.name.clone()))];
+        match &self.args {
+            Args::Values(values) => items.extend(values.iter().cloned()),
+            Args::Forms(call) => items.extend(call[1..].iter().map(|form| {
+                quote::datum(form).unwrap_or(Result::Primitive(Primitive::Sym("...".to_string())))
+            })),
+        }
+
+        pair::list(items)
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_list())?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+
+        Ok(())
+    }
+}
+
+thread_local! {
+    // Calls in progress, innermost last. Frames are left in place when an error
+    // unwinds through them, so they can be shown once it's caught or reaches the
+    // top
+    static STACK: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn depth() -> usize {
+    STACK.with(|stack| stack.borrow().len())
+}
+
+pub fn push(frame: Frame) {
+    STACK.with(|stack| stack.borrow_mut().push(frame))
+}
+
+// Drops the frame at depth, keeping any above it
+pub fn remove(depth: usize) {
+    STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        if depth < stack.len() {
+            stack.remove(depth);
+        }
+    })
+}
+
+// Drops every frame above depth, for calls that have returned or errors that
+// have been caught
+pub fn truncate(depth: usize) {
+    STACK.with(|stack| stack.borrow_mut().truncate(depth))
+}
+
+// Takes every frame above depth off the stack, in the order they were pushed
+pub fn split_off(depth: usize) -> Vec<Frame> {
+    STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        let depth = depth.min(stack.len());
+        stack.split_off(depth)
+    })
+}
+
+// Puts frames taken by split_off back on top of the stack
+pub fn extend(frames: Vec<Frame>) {
+    STACK.with(|stack| stack.borrow_mut().extend(frames))
+}
+
+// The calls in progress, innermost first
+pub fn frames() -> Vec<Frame> {
+    STACK.with(|stack| stack.borrow().iter().rev().cloned().collect())
+}
+
+// Like frames, also emptying the stack. For the top level once an error has
+// reached it
+pub fn take() -> Vec<Frame> {
+    STACK.with(|stack| stack.take().into_iter().rev().collect())
+}
+
+// How many frames a backtrace shows from each end of a deep stack
+const SHOWN: usize = 20;
+
+// Lists frames innermost first. Only the ends of a deep stack are shown, since
+// the middle of runaway recursion is the same calls over and over
+pub fn backtrace(frames: &[Frame]) -> String {
+    let mut out = "Backtrace (most recent call first):".to_string();
+    for (i, frame) in frames.iter().enumerate() {
+        if frames.len() > 2 * SHOWN && i == SHOWN {
+            out.push_str(&format!("\n  ... {} more", frames.len() - 2 * SHOWN));
+        }
+        if frames.len() <= 2 * SHOWN || i < SHOWN || i >= frames.len() - SHOWN {
+            out.push_str(&format!("\n  {}: {}", i, frame));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::test_util::eval;
+
+    // The calls an uncaught error unwound through
+    fn trace_of(source: &str) -> Vec<String> {
+        assert!(eval(source).is_err());
+        take()
+            .iter()
+            .map(|frame| frame.to_list().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn errors_keep_the_calls_they_unwound_through() {
+        assert_eq!(
+            trace_of("(define (f x) (car x)) (define (g y) (+ 1 (f y))) (g 5)"),
+            vec!["(car x)", "(f 5)", "(+ 1 (f y))", "(g 5)"]
+        );
+    }
+
+    #[test]
+    fn frames_record_where_calls_were_made() {
+        assert!(eval("(define (f x) (car x))\n(f 1)").is_err());
+
+        let frames = take();
+        assert_eq!(
+            frames.iter().map(|f| f.to_string()).collect::<Vec<_>>(),
+            vec!["(car x) at <repl>:1:15", "(f 1) at <repl>:2:1"]
+        );
+        assert_eq!(
+            backtrace(&frames),
+            "Backtrace (most recent call first):\n  0: (car x) at <repl>:1:15\n  1: (f 1) at <repl>:2:1"
+        );
+    }
+
+    #[test]
+    fn frames_are_named_by_define() {
+        assert_eq!(
+            trace_of("(define f (lambda (x) (car x))) (f 1)"),
+            vec!["(car x)", "(f 1)"]
+        );
+        assert_eq!(
+            trace_of("((lambda (x #:y (y 2)) (car x)) 1 #:y 3)"),
+            vec!["(car x)", "(lambda 1 #:y 3)"]
+        );
+    }
+
+    #[test]
+    fn tail_calls_replace_their_callers_frame() {
+        assert_eq!(
+            trace_of("(define (count n) (if (= n 0) (car n) (count (- n 1)))) (count 100)"),
+            vec!["(car n)", "(count 0)"]
+        );
+    }
+
+    #[test]
+    fn returned_and_caught_calls_leave_the_stack() {
+        assert!(eval("(define (f x) x) (f 1) (guard (e (#t e)) (f (car 1)))").is_ok());
+        assert!(take().is_empty());
+
+        assert_eq!(
+            trace_of("(guard (e (#f e)) (car 1))"),
+            vec!["(car 1)", "(guard (e (#f e)) (car 1))"]
+        );
+    }
+
+    #[test]
+    fn backtraces_elide_the_middle_of_deep_stacks() {
+        assert!(eval("(define (f n) (if (= n 0) (car n) (+ 1 (f (- n 1))))) (f 50)").is_err());
+
+        let out = backtrace(&take());
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 * SHOWN + 1);
+        assert_eq!(lines[1], "  0: (car n) at <repl>:1:27");
+        assert_eq!(lines[SHOWN + 1], "  ... 62 more");
+        assert_eq!(lines[lines.len() - 1], "  101: (f 50) at <repl>:1:55");
+    }
+}
@@ -1,9 +1,26 @@
-use crate::lang::{ast::AstNode, scope::Scope, types::result::Result};
+use crate::lang::{
+    ast::AstNode,
+    exec::Tail,
+    scope::Scope,
+    types::{error::RiskResult, result::Result},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum BuiltinFn {
+    // Runs to completion and produces a value
+    Value(fn(Vec<AstNode>, &mut Scope) -> RiskResult<Option<Result>>),
+    // May hand an expression back to the evaluator to run in tail position, so
+    // forms like if don't grow the Rust stack
+    Tail(fn(Vec<AstNode>, &mut Scope) -> RiskResult<Tail>),
+}
 
 #[derive(Debug, Clone)]
 pub struct Builtin {
     pub id: String,
-    pub f: fn(Vec<AstNode>, &mut Scope) -> Option<Result>,
+    pub f: BuiltinFn,
+    // Special forms like if and define, which use their arguments as code
+    // rather than as values, so can't be applied to values
+    pub form: bool,
 }
 
 impl PartialEq for Builtin {
@@ -20,11 +37,13 @@ mod tests {
     fn eq_on_same_id() {
         let l = Builtin {
             id: "id".to_string(),
-            f: |_v: Vec<AstNode>, _s: &mut Scope| None,
+            f: BuiltinFn::Value(|_v: Vec<AstNode>, _s: &mut Scope| Ok(None)),
+            form: false,
         };
         let r = Builtin {
             id: "id".to_string(),
-            f: |_v: Vec<AstNode>, _s: &mut Scope| None,
+            f: BuiltinFn::Tail(|_v: Vec<AstNode>, _s: &mut Scope| Ok(Tail::Value(None))),
+            form: true,
         };
         assert!(l == r);
     }
@@ -33,11 +52,13 @@ mod tests {
     fn not_eq_based_on_id() {
         let l = Builtin {
             id: "id".to_string(),
-            f: |_v: Vec<AstNode>, _s: &mut Scope| None,
+            f: BuiltinFn::Value(|_v: Vec<AstNode>, _s: &mut Scope| Ok(None)),
+            form: false,
         };
         let r = Builtin {
             id: "id2".to_string(),
-            f: |_v: Vec<AstNode>, _s: &mut Scope| None,
+            f: BuiltinFn::Value(|_v: Vec<AstNode>, _s: &mut Scope| Ok(None)),
+            form: false,
         };
         assert!(l != r);
     }
@@ -0,0 +1,10 @@
+use crate::lang::types::result::Result;
+
+// An error as a value, made by error or by catching a failure in a builtin
+#[derive(PartialEq, Debug, Clone)]
+pub struct Condition {
+    pub message: String,
+    // Values the error is about, kept apart from the message so handlers can
+    // inspect them
+    pub irritants: Vec<Result>,
+}
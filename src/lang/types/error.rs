@@ -0,0 +1,161 @@
+use std::fmt::Display;
+
+use crate::lang::{
+    token::Span,
+    types::{condition::Condition, result::Result},
+};
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum RiskError {
+    // Malformed source or special form
+    Syntax(String, Option<Span>),
+    // Lookup of a name with no binding
+    Unbound(String, Option<Span>),
+    // Wrong number of arguments to a function or builtin
+    Arity(String, Option<Span>),
+    // Value of the wrong type for an operation
+    Type(String, Option<Span>),
+    // Anything else that goes wrong while evaluating
+    Runtime(String, Option<Span>),
+    // A value passed to raise, which guard can catch
+    Raised(Box<Result>, Option<Span>),
+    // Not a failure, but a continuation being called with a value. It unwinds
+    // the stack until it reaches the call/cc that made the continuation
+    Escape(usize, Box<Option<Result>>),
+}
+
+pub type RiskResult<T> = std::result::Result<T, RiskError>;
+
+impl RiskError {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            RiskError::Syntax(_, span)
+            | RiskError::Unbound(_, span)
+            | RiskError::Arity(_, span)
+            | RiskError::Type(_, span)
+            | RiskError::Runtime(_, span)
+            | RiskError::Raised(_, span) => span.as_ref(),
+            RiskError::Escape(..) => None,
+        }
+    }
+
+    // Attach a location to an error that doesn't have one yet. The innermost
+    // location wins, so errors keep pointing at the most specific expression.
+    // Code the interpreter makes up has no real location, so those are replaced
+    pub fn with_span(mut self, at: &Span) -> RiskError {
+        match &mut self {
+            RiskError::Syntax(_, span)
+            | RiskError::Unbound(_, span)
+            | RiskError::Arity(_, span)
+            | RiskError::Type(_, span)
+            | RiskError::Runtime(_, span)
+            | RiskError::Raised(_, span) => {
+                if span.as_ref().is_none_or(|s| *s == Span::default()) {
+                    *span = Some(at.clone())
+                }
+            }
+            RiskError::Escape(..) => (),
+        }
+
+        self
+    }
+
+    // The value a guard sees when it catches this error. Builtin failures
+    // become error objects describing them, while continuations escaping
+    // through aren't errors at all and can't be caught
+    pub fn condition(&self) -> Option<Result> {
+        match self {
+            RiskError::Raised(value, _) => Some(*value.clone()),
+            RiskError::Escape(..) => None,
+            _ => Some(Result::Condition(Condition {
+                message: self.describe(),
+                irritants: Vec::new(),
+            })),
+        }
+    }
+
+    // What went wrong, without where
+    fn describe(&self) -> String {
+        match self {
+            RiskError::Syntax(msg, _) => format!("Syntax error: {}", msg),
+            RiskError::Unbound(name, _) => format!("Unbound variable: {}", name),
+            RiskError::Arity(msg, _) => format!("Arity error: {}", msg),
+            RiskError::Type(msg, _) => format!("Type error: {}", msg),
+            RiskError::Runtime(msg, _) => format!("Runtime error: {}", msg),
+            RiskError::Raised(value, _) => match value.as_ref() {
+                Result::Condition(Condition { message, irritants }) => {
+                    let mut described = format!("Error: {}", message);
+                    for irritant in irritants {
+                        described.push_str(&format!(" {}", irritant));
+                    }
+                    described
+                }
+                value => format!("Uncaught exception: {}", value),
+            },
+            // Only reaches the top if its call/cc has already returned
+            RiskError::Escape(..) => {
+                "Runtime error: continuation called after its call/cc returned".to_string()
+            }
+        }
+    }
+}
+
+impl Display for RiskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(span) = self.span() {
+            write!(f, "{}: ", span)?;
+        }
+
+        write!(f, "{}", self.describe())
+    }
+}
+
+impl std::error::Error for RiskError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn span(line: usize, col: usize) -> Span {
+        Span {
+            file: Rc::from("main.risk"),
+            line,
+            col,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    #[test]
+    fn display_includes_category() {
+        assert_eq!(
+            RiskError::Unbound("x".to_string(), None).to_string(),
+            "Unbound variable: x"
+        );
+        assert_eq!(
+            RiskError::Syntax("unexpected ')'".to_string(), None).to_string(),
+            "Syntax error: unexpected ')'"
+        );
+    }
+
+    #[test]
+    fn display_includes_location() {
+        assert_eq!(
+            RiskError::Unbound("x".to_string(), Some(span(3, 7))).to_string(),
+            "main.risk:3:7: Unbound variable: x"
+        );
+    }
+
+    #[test]
+    fn with_span_keeps_innermost_location() {
+        let e = RiskError::Type("bad".to_string(), Some(span(1, 1))).with_span(&span(2, 2));
+        assert_eq!(e.span(), Some(&span(1, 1)));
+
+        let e = RiskError::Type("bad".to_string(), None).with_span(&span(2, 2));
+        assert_eq!(e.span(), Some(&span(2, 2)));
+
+        let e = RiskError::Type("bad".to_string(), Some(Span::default())).with_span(&span(2, 2));
+        assert_eq!(e.span(), Some(&span(2, 2)));
+    }
+}
@@ -1,4 +1,8 @@
 pub mod builtin;
+pub mod condition;
+pub mod error;
+pub mod pair;
 pub mod primitive;
 pub mod result;
+pub mod syntax;
 pub mod userfunc;
@@ -0,0 +1,129 @@
+use std::{mem, rc::Rc};
+
+use crate::lang::types::result::Result;
+
+// A cons cell. Proper lists are chains of pairs whose last cdr is Result::Nil
+#[derive(Debug, Clone)]
+pub struct Pair {
+    pub car: Result,
+    pub cdr: Result,
+}
+
+impl Pair {
+    pub fn cons(car: Result, cdr: Result) -> Result {
+        Result::Pair(Rc::new(Pair { car, cdr }))
+    }
+}
+
+// Lists can be far longer, and nested far deeper, than the host stack allows
+// recursing into, so pairs are compared and dropped by working through a stack
+// of the pairs still to visit
+
+impl PartialEq for Pair {
+    fn eq(&self, other: &Pair) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some((l, r)) = pending.pop() {
+            for (l, r) in [(&l.car, &r.car), (&l.cdr, &r.cdr)] {
+                match (l, r) {
+                    (Result::Pair(l), Result::Pair(r)) => pending.push((l, r)),
+                    (l, r) if l != r => return false,
+                    _ => (),
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl Drop for Pair {
+    fn drop(&mut self) {
+        if !matches!(self.car, Result::Pair(_)) && !matches!(self.cdr, Result::Pair(_)) {
+            return;
+        }
+
+        // Pairs held only here are emptied before they're dropped, so dropping
+        // them doesn't recurse. Shared ones just lose a reference
+        let mut pending = vec![
+            mem::replace(&mut self.car, Result::Nil),
+            mem::replace(&mut self.cdr, Result::Nil),
+        ];
+        while let Some(value) = pending.pop() {
+            if let Result::Pair(pair) = value
+                && let Ok(mut pair) = Rc::try_unwrap(pair)
+            {
+                pending.push(mem::replace(&mut pair.car, Result::Nil));
+                pending.push(mem::replace(&mut pair.cdr, Result::Nil));
+            }
+        }
+    }
+}
+
+// Builds a proper list out of items
+pub fn list(items: Vec<Result>) -> Result {
+    list_with_tail(items, Result::Nil)
+}
+
+// Builds a list out of items ending in tail rather than Nil
+pub fn list_with_tail(items: Vec<Result>, tail: Result) -> Result {
+    items
+        .into_iter()
+        .rev()
+        .fold(tail, |cdr, car| Pair::cons(car, cdr))
+}
+
+// Items of a proper list, or None if value isn't one
+pub fn to_vec(value: &Result) -> Option<Vec<Result>> {
+    let mut items = Vec::new();
+    let mut current = value;
+
+    loop {
+        match current {
+            Result::Nil => return Some(items),
+            Result::Pair(pair) => {
+                items.push(pair.car.clone());
+                current = &pair.cdr;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::types::primitive::Primitive;
+
+    fn int(i: i32) -> Result {
+        Result::Primitive(Primitive::I(i))
+    }
+
+    #[test]
+    fn list_round_trips_through_to_vec() {
+        let items = vec![int(1), int(2), int(3)];
+
+        assert_eq!(to_vec(&list(items.clone())), Some(items));
+        assert_eq!(to_vec(&list(vec![])), Some(vec![]));
+    }
+
+    #[test]
+    fn to_vec_rejects_improper_lists() {
+        assert_eq!(to_vec(&list_with_tail(vec![int(1)], int(2))), None);
+        assert_eq!(to_vec(&int(1)), None);
+    }
+
+    #[test]
+    fn long_lists_compare_print_and_drop_without_overflowing() {
+        let long = || list((0..200_000).map(int).collect());
+        assert_eq!(long(), long());
+        assert_ne!(long(), list((0..199_999).map(int).collect()));
+
+        let mut nested = Result::Nil;
+        for _ in 0..200_000 {
+            nested = list(vec![nested]);
+        }
+        assert_eq!(nested.clone(), nested);
+        assert!(nested.to_string().starts_with("((((("));
+        drop(nested);
+    }
+}
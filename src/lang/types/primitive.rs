@@ -3,4 +3,8 @@ pub enum Primitive {
     I(i32),
     F(f64),
     B(bool),
+    S(String),
+    Sym(String),
+    // A keyword, held without its leading #:
+    Kw(String),
 }
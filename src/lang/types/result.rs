@@ -1,12 +1,37 @@
-use std::fmt::Display;
+use std::{fmt::Display, rc::Rc};
 
-use crate::lang::types::{builtin::Builtin, primitive::Primitive, userfunc::FnDef};
+use crate::lang::scope::Scope;
+use crate::lang::types::{
+    builtin::Builtin,
+    condition::Condition,
+    pair::Pair,
+    primitive::Primitive,
+    syntax::{Macro, Rules},
+    userfunc::FnDef,
+};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Result {
     Primitive(Primitive),
     Builtin(Builtin),
     FnDef(FnDef),
+    // Called on the unevaluated code of its arguments
+    Macro(Macro),
+    // A scope that code can be evaluated in
+    Environment(Scope),
+    // Calling it returns its argument from the call/cc with the same id
+    Continuation(usize),
+    // An error object
+    Condition(Condition),
+    Pair(Rc<Pair>),
+    // The empty list
+    Nil,
+}
+
+// Part of a list still to be written
+enum Written<'a> {
+    Value(&'a Result),
+    Text(&'static str),
 }
 
 impl Display for Result {
@@ -19,11 +44,131 @@ impl Display for Result {
                     true => write!(f, "#t"),
                     false => write!(f, "#f"),
                 },
+                Primitive::S(s) => {
+                    write!(f, "\"")?;
+                    for ch in s.chars() {
+                        match ch {
+                            '"' => write!(f, "\\\"")?,
+                            '\\' => write!(f, "\\\\")?,
+                            '\n' => write!(f, "\\n")?,
+                            '\t' => write!(f, "\\t")?,
+                            _ => write!(f, "{}", ch)?,
+                        }
+                    }
+                    write!(f, "\"")
+                }
+                Primitive::Sym(s) => write!(f, "{}", s),
+                Primitive::Kw(k) => write!(f, "#:{}", k),
             },
             Result::Builtin(func) => write!(f, "builtin#{}", func.id),
             Result::FnDef(fn_def) => {
-                write!(f, "userfuncdef#{:?}{:?}", fn_def.params, fn_def.body)
+                write!(
+                    f,
+                    "userfuncdef#{:?}{:?}",
+                    fn_def.params.names(),
+                    fn_def.body
+                )
+            }
+            Result::Macro(Macro::Function(fn_def)) => {
+                write!(f, "macro#{:?}{:?}", fn_def.params.names(), fn_def.body)
+            }
+            Result::Macro(Macro::Rules(Rules {
+                literals, rules, ..
+            })) => {
+                write!(f, "syntax-rules#{:?}{:?}", literals, rules)
+            }
+            Result::Environment(_) => write!(f, "environment#"),
+            Result::Continuation(id) => write!(f, "continuation#{}", id),
+            Result::Condition(Condition { message, irritants }) => {
+                write!(f, "error#{}", message)?;
+                for irritant in irritants {
+                    write!(f, " {}", irritant)?;
+                }
+                Ok(())
+            }
+            // Lists inside lists are written from a stack of what's left to
+            // write rather than by recursion, so deep nesting can't overflow
+            Result::Pair(_) => {
+                let mut pending = vec![Written::Value(self)];
+                while let Some(next) = pending.pop() {
+                    let pair = match next {
+                        Written::Text(text) => {
+                            write!(f, "{}", text)?;
+                            continue;
+                        }
+                        Written::Value(Result::Pair(pair)) => pair,
+                        Written::Value(value) => {
+                            write!(f, "{}", value)?;
+                            continue;
+                        }
+                    };
+
+                    // Pushed in reverse, to come off the stack in order
+                    let mut items = vec![&pair.car];
+                    let mut rest = &pair.cdr;
+                    while let Result::Pair(next) = rest {
+                        items.push(&next.car);
+                        rest = &next.cdr;
+                    }
+
+                    write!(f, "(")?;
+                    pending.push(Written::Text(")"));
+                    if !matches!(rest, Result::Nil) {
+                        pending.push(Written::Value(rest));
+                        pending.push(Written::Text(" . "));
+                    }
+                    for (i, item) in items.into_iter().enumerate().rev() {
+                        pending.push(Written::Value(item));
+                        if i > 0 {
+                            pending.push(Written::Text(" "));
+                        }
+                    }
+                }
+
+                Ok(())
             }
+            Result::Nil => write!(f, "()"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::types::pair;
+
+    #[test]
+    fn display_primitives() {
+        assert_eq!(Result::Primitive(Primitive::I(1)).to_string(), "1");
+        assert_eq!(Result::Primitive(Primitive::F(1.5)).to_string(), "1.5");
+        assert_eq!(Result::Primitive(Primitive::B(false)).to_string(), "#f");
+    }
+
+    #[test]
+    fn display_strings_quoted_and_escaped() {
+        assert_eq!(
+            Result::Primitive(Primitive::S("say \"hi\"\n\t\\ é".to_string())).to_string(),
+            r#""say \"hi\"\n\t\\ é""#
+        );
+    }
+
+    #[test]
+    fn display_lists_and_dotted_pairs() {
+        let int = |i| Result::Primitive(Primitive::I(i));
+
+        assert_eq!(Result::Nil.to_string(), "()");
+        assert_eq!(
+            pair::list(vec![int(1), int(2), int(3)]).to_string(),
+            "(1 2 3)"
+        );
+        assert_eq!(Pair::cons(int(1), int(2)).to_string(), "(1 . 2)");
+        assert_eq!(
+            pair::list_with_tail(vec![int(1), int(2)], int(3)).to_string(),
+            "(1 2 . 3)"
+        );
+        assert_eq!(
+            pair::list(vec![pair::list(vec![int(1)]), Result::Nil]).to_string(),
+            "((1) ())"
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use crate::lang::{ast::AstNode, scope::Scope, types::userfunc::FnDef};
+
+// Rewrites the code of a call before it is evaluated
+#[derive(PartialEq, Debug, Clone)]
+pub enum Macro {
+    // Made by defmacro, called with the arguments as data and returning code
+    Function(FnDef),
+    // Made by syntax-rules
+    Rules(Rules),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Rules {
+    // Symbols that only match themselves in a pattern
+    pub literals: Vec<String>,
+    // Pattern and template pairs, tried in order
+    pub rules: Vec<(AstNode, AstNode)>,
+    // Where the macro was defined. Symbols a template introduces without
+    // binding them refer to what they do here
+    pub env: Scope,
+}
@@ -1,41 +1,272 @@
-use crate::lang::{ast::AstNode, exec::Program, scope::Scope, types::result::Result};
+use crate::lang::{
+    ast::AstNode,
+    base::{eval_arg, tail_sequence},
+    exec::{self, Program, Tail},
+    scope::{Scope, original_name},
+    token::Span,
+    types::{
+        error::{RiskError, RiskResult},
+        pair,
+        primitive::Primitive,
+        result::Result,
+    },
+};
+
+// What a function accepts, in the order arguments are bound
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Params {
+    pub required: Vec<String>,
+    // Parameters that may be left off, each with an expression giving its
+    // default. Defaults are evaluated when the function is called, in its own
+    // scope, so they can refer to the parameters before them
+    pub optional: Vec<(String, AstNode)>,
+    // Parameters passed as #:name value. Any that aren't passed take their
+    // default, which is evaluated like an optional parameter's
+    pub keywords: Vec<KeywordParam>,
+    // Collects any remaining arguments into a list
+    pub rest: Option<String>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct KeywordParam {
+    // The name callers pass the argument by
+    pub keyword: String,
+    // The variable it's bound to. The same as keyword unless a macro expansion
+    // renamed the parameter, which leaves callers passing it by its written name
+    pub name: String,
+    pub default: AstNode,
+}
+
+impl Params {
+    pub fn positional(required: Vec<String>) -> Params {
+        Params {
+            required,
+            ..Params::default()
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.required
+            .iter()
+            .chain(self.optional.iter().map(|(name, _)| name))
+            .chain(self.keywords.iter().map(|param| &param.name))
+            .chain(self.rest.iter())
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    // How many arguments are accepted, for error messages
+    fn expected(&self) -> String {
+        let min = self.required.len();
+        let max = min + self.optional.len();
+
+        match self.rest {
+            Some(_) => format!("at least {}", min),
+            None if min == max => min.to_string(),
+            None => format!("{} to {}", min, max),
+        }
+    }
+}
+
+// Arguments passed as #:name value, by name
+pub type KeywordArgs = Vec<(String, Result)>;
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct FnDef {
-    pub params: Vec<String>,
-    pub body: AstNode,
+    pub params: Params,
+    // Expressions evaluated in order, the last one's value is returned
+    pub body: Vec<AstNode>,
+    // Environment the function was defined in, the body is evaluated in a child
+    // of this rather than of the caller's scope
+    pub env: Scope,
+    // The name it was defined under, for backtraces
+    pub name: Option<String>,
 }
 
 impl FnDef {
-    pub fn exec(&self, args: Vec<AstNode>, scope: &mut Scope) -> Option<Result> {
-        // todo: explore something better than duplicating the entire scope
-        let mut local_scope = scope.clone();
-        if args.len() != self.params.len() {
-            panic!(
-                "Incorrect number of arguments provided. Expected {}, received {}",
-                self.params.len(),
-                args.len()
-            )
+    pub fn exec(&self, args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Option<Result>> {
+        exec::resolve(self.exec_tail(args, scope)?)
+    }
+
+    // Evaluates the arguments in the caller's scope, then binds them as prepare
+    // does
+    pub fn exec_tail(&self, args: Vec<AstNode>, scope: &mut Scope) -> RiskResult<Tail> {
+        let (values, keywords) = self.eval_args(&args, scope)?;
+        self.prepare_keywords(values, keywords)
+    }
+
+    // Evaluates arguments in the caller's scope, splitting off those passed by
+    // keyword. #:name value pairs may come in any order
+    pub fn eval_args(
+        &self,
+        args: &[AstNode],
+        scope: &mut Scope,
+    ) -> RiskResult<(Vec<Result>, KeywordArgs)> {
+        let mut values = Vec::new();
+        let mut keywords: KeywordArgs = Vec::new();
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let (keyword, expr) = match arg {
+                AstNode::Keyword(k, span) => {
+                    check_unique(original_name(k), &keywords, Some(span))?;
+                    match iter.next() {
+                        Some(expr) => (Some(k), expr),
+                        None => return Err(missing_value(k, Some(span))),
+                    }
+                }
+                _ => (None, arg),
+            };
+
+            let value = match Program::new(expr.clone(), scope).exec()? {
+                Some(s) => s,
+                None => {
+                    return Err(RiskError::Runtime(
+                        "Cannot pass none to function".to_string(),
+                        Some(expr.span().clone()),
+                    ));
+                }
+            };
+
+            match keyword {
+                Some(k) => keywords.push((original_name(k).to_string(), value)),
+                None => values.push(value),
+            }
+        }
+
+        Ok((values, keywords))
+    }
+
+    // Like eval_args for arguments that have already been evaluated, as apply
+    // passes them. Keyword values mark #:name value pairs just as keywords in
+    // source do
+    pub fn split_keywords(args: Vec<Result>) -> RiskResult<(Vec<Result>, KeywordArgs)> {
+        let mut values = Vec::new();
+        let mut keywords: KeywordArgs = Vec::new();
+
+        let mut iter = args.into_iter();
+        while let Some(arg) = iter.next() {
+            match arg {
+                Result::Primitive(Primitive::Kw(k)) => {
+                    check_unique(&k, &keywords, None)?;
+                    match iter.next() {
+                        Some(value) => keywords.push((k, value)),
+                        None => return Err(missing_value(&k, None)),
+                    }
+                }
+                arg => values.push(arg),
+            }
+        }
+
+        Ok((values, keywords))
+    }
+
+    // Runs the function with arguments that have already been evaluated
+    pub fn call(&self, args: Vec<Result>) -> RiskResult<Option<Result>> {
+        exec::resolve(self.prepare(args)?)
+    }
+
+    // Binds arguments and evaluates all but the last body expression, leaving
+    // that to be evaluated in tail position
+    pub fn prepare(&self, args: Vec<Result>) -> RiskResult<Tail> {
+        self.prepare_keywords(args, Vec::new())
+    }
+
+    // Like prepare, also binding arguments passed by keyword
+    pub fn prepare_keywords(
+        &self,
+        args: Vec<Result>,
+        mut keywords: KeywordArgs,
+    ) -> RiskResult<Tail> {
+        let params = &self.params;
+        let max = params.required.len() + params.optional.len();
+        if args.len() < params.required.len() || (params.rest.is_none() && args.len() > max) {
+            return Err(RiskError::Arity(
+                format!(
+                    "Incorrect number of arguments provided. Expected {}, received {}",
+                    params.expected(),
+                    args.len()
+                ),
+                None,
+            ));
+        }
+
+        if let Some((unknown, _)) = keywords
+            .iter()
+            .find(|(k, _)| !params.keywords.iter().any(|param| &param.keyword == k))
+        {
+            return Err(RiskError::Arity(
+                format!("Function does not take keyword #:{}", unknown),
+                None,
+            ));
         }
 
-        for (i, param) in self.params.clone().iter().enumerate() {
-            match Program::new(args[i].clone(), scope).exec() {
-                Some(s) => local_scope.map.insert(param.to_string(), s),
-                None => panic!("Cannot pass none to function"),
+        let mut local_scope = self.env.child();
+        let mut args = args.into_iter();
+        for param in params.required.iter() {
+            local_scope.define(param, args.next().expect("arity was checked"));
+        }
+        for (param, default) in params.optional.iter() {
+            let value = match args.next() {
+                Some(arg) => arg,
+                None => eval_arg(default, &mut local_scope)?,
             };
+            local_scope.define(param, value);
+        }
+        for param in params.keywords.iter() {
+            let value = match keywords.iter().position(|(k, _)| k == &param.keyword) {
+                Some(i) => keywords.swap_remove(i).1,
+                None => eval_arg(&param.default, &mut local_scope)?,
+            };
+            local_scope.define(&param.name, value);
+        }
+        if let Some(rest) = &params.rest {
+            local_scope.define(rest, pair::list(args.collect()));
         }
 
-        Program::new(self.body.clone(), &mut local_scope).exec()
+        tail_sequence(&self.body, &mut local_scope)
+    }
+
+    // A function taking exactly the given parameters
+    pub fn new(params: Vec<String>, body: Vec<AstNode>, env: Scope) -> FnDef {
+        FnDef::with_params(Params::positional(params), body, env)
+    }
+
+    pub fn with_params(params: Params, body: Vec<AstNode>, env: Scope) -> FnDef {
+        FnDef {
+            params,
+            body,
+            env,
+            name: None,
+        }
     }
 
-    pub fn new(params: Vec<String>, body: AstNode) -> FnDef {
+    pub fn named(self, name: &str) -> FnDef {
         FnDef {
-            params: params,
-            body: body,
+            name: Some(original_name(name).to_string()),
+            ..self
         }
     }
 }
 
+// Errors if keyword k has already been passed
+fn check_unique(k: &str, keywords: &KeywordArgs, span: Option<&Span>) -> RiskResult<()> {
+    match keywords.iter().any(|(name, _)| name == k) {
+        true => Err(RiskError::Arity(
+            format!("Keyword #:{} passed more than once", k),
+            span.cloned(),
+        )),
+        false => Ok(()),
+    }
+}
+
+fn missing_value(k: &str, span: Option<&Span>) -> RiskError {
+    RiskError::Arity(
+        format!("Keyword #:{} must be followed by a value", k),
+        span.cloned(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,47 +274,46 @@ mod tests {
 
     #[test]
     fn fn_def_exec_with_no_params_or_args() {
-        let f = FnDef::new(vec![], AstNode::Leaf("2".to_string()));
+        let f = FnDef::new(vec![], vec![AstNode::leaf("2")], Scope::base());
 
         assert_eq!(
-            f.exec(vec![], &mut Scope::base()).unwrap(),
+            f.exec(vec![], &mut Scope::base()).unwrap().unwrap(),
             Result::Primitive(Primitive::I(2))
         )
     }
 
     #[test]
-    #[should_panic]
     fn fn_def_fails_with_mismatched_params_and_args() {
         let f = FnDef::new(
             vec!["x".to_string(), "y".to_string()],
-            AstNode::Leaf("2".to_string()),
+            vec![AstNode::leaf("2")],
+            Scope::base(),
         );
 
-        assert_eq!(
-            f.exec(vec![], &mut Scope::base()).unwrap(),
-            Result::Primitive(Primitive::I(2))
-        )
+        assert!(matches!(
+            f.exec(vec![], &mut Scope::base()),
+            Err(RiskError::Arity(..))
+        ))
     }
 
     #[test]
     fn fn_def_applies_args_to_params() {
         let f = FnDef::new(
             vec!["x".to_string(), "y".to_string()],
-            AstNode::AST(vec![
-                AstNode::Leaf("eq?".to_string()),
-                AstNode::Leaf("x".to_string()),
-                AstNode::Leaf("y".to_string()),
-            ]),
+            vec![AstNode::list(vec![
+                AstNode::leaf("eq?"),
+                AstNode::leaf("x"),
+                AstNode::leaf("y"),
+            ])],
+            Scope::base(),
         );
 
         assert_eq!(
             f.exec(
-                vec![
-                    AstNode::Leaf("1".to_string()),
-                    AstNode::Leaf("1".to_string())
-                ],
+                vec![AstNode::leaf("1"), AstNode::leaf("1")],
                 &mut Scope::base()
             )
+            .unwrap()
             .unwrap(),
             Result::Primitive(Primitive::B(true))
         )
@@ -93,27 +323,81 @@ mod tests {
     fn fn_def_args_can_evaluate_themselves() {
         let f = FnDef::new(
             vec!["x".to_string(), "y".to_string()],
-            AstNode::AST(vec![
-                AstNode::Leaf("eq?".to_string()),
-                AstNode::Leaf("x".to_string()),
-                AstNode::Leaf("y".to_string()),
-            ]),
+            vec![AstNode::list(vec![
+                AstNode::leaf("eq?"),
+                AstNode::leaf("x"),
+                AstNode::leaf("y"),
+            ])],
+            Scope::base(),
         );
 
         assert_eq!(
             f.exec(
                 vec![
-                    AstNode::AST(vec![
-                        AstNode::Leaf("if".to_string()),
-                        AstNode::Leaf("#t".to_string()),
-                        AstNode::Leaf("1".to_string()),
+                    AstNode::list(vec![
+                        AstNode::leaf("if"),
+                        AstNode::leaf("#t"),
+                        AstNode::leaf("1"),
                     ]),
-                    AstNode::Leaf("1".to_string())
+                    AstNode::leaf("1")
                 ],
                 &mut Scope::base()
             )
+            .unwrap()
             .unwrap(),
             Result::Primitive(Primitive::B(true))
         )
     }
+
+    #[test]
+    fn fn_def_body_evaluates_in_order_and_returns_last() {
+        let f = FnDef::new(
+            vec!["x".to_string()],
+            vec![
+                AstNode::list(vec![
+                    AstNode::leaf("define"),
+                    AstNode::leaf("y"),
+                    AstNode::leaf("x"),
+                ]),
+                AstNode::list(vec![AstNode::leaf("not"), AstNode::leaf("y")]),
+            ],
+            Scope::base(),
+        );
+
+        assert_eq!(
+            f.exec(vec![AstNode::leaf("#f")], &mut Scope::base())
+                .unwrap()
+                .unwrap(),
+            Result::Primitive(Primitive::B(true))
+        )
+    }
+
+    #[test]
+    fn fn_def_arity_errors_describe_accepted_counts() {
+        let params = Params {
+            required: vec!["x".to_string()],
+            optional: vec![("y".to_string(), AstNode::leaf("1"))],
+            keywords: vec![],
+            rest: None,
+        };
+        let f = FnDef::with_params(params.clone(), vec![AstNode::leaf("x")], Scope::base());
+
+        assert_eq!(
+            f.call(vec![]).unwrap_err().to_string(),
+            "Arity error: Incorrect number of arguments provided. Expected 1 to 2, received 0"
+        );
+
+        let f = FnDef::with_params(
+            Params {
+                rest: Some("z".to_string()),
+                ..params
+            },
+            vec![AstNode::leaf("z")],
+            Scope::base(),
+        );
+        assert_eq!(
+            f.call(vec![]).unwrap_err().to_string(),
+            "Arity error: Incorrect number of arguments provided. Expected at least 1, received 0"
+        );
+    }
 }
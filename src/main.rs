@@ -1,41 +1,257 @@
 use crate::lang::{
-    ast,
+    ast::{self, AstNode},
     exec::{self},
     scope::Scope,
-    token,
-    types::result::Result,
+    token, trace,
+    types::{
+        error::{RiskError, RiskResult},
+        pair,
+        primitive::Primitive,
+        result::Result,
+        userfunc::FnDef,
+    },
+};
+use std::{
+    env, fs,
+    io::{self, BufRead, Read, Write},
+    process, thread,
 };
-use std::io;
 
 pub mod lang;
 
+const USAGE: &str = "Usage: risk                       start a REPL
+       risk <file> [args...]      run a file
+       risk -e <expr> [args...]   evaluate an expression and print its value
+       risk - [args...]           run a program read from stdin and print its value";
+
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Calls outside tail position nest on the host stack, so evaluation runs on
+    // a thread with room for as many as the evaluator allows. A panic has
+    // already been reported by the time it's joined, so only the code is left
+    let code = thread::Builder::new()
+        .stack_size(exec::STACK_SIZE)
+        .spawn(move || start(&args))
+        .expect("Could not start the interpreter")
+        .join()
+        .unwrap_or(101);
+
+    process::exit(code)
+}
+
+// Does what the command-line arguments ask, returning the exit code
+fn start(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        None => {
+            repl(io::stdin().lock(), &mut io::stdout());
+            0
+        }
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            0
+        }
+        Some(flag @ ("-e" | "--eval")) => match args.get(1) {
+            Some(expr) => {
+                let args = [&["<eval>".to_string()], &args[2..]].concat();
+                run(expr, "<eval>", &args, Some(&mut io::stdout()))
+            }
+            None => {
+                eprintln!("{} must be followed by an expression\n{}", flag, USAGE);
+                2
+            }
+        },
+        Some("-") => run_stdin(args),
+        Some(flag) if flag.starts_with('-') => {
+            eprintln!("Unknown option {}\n{}", flag, USAGE);
+            2
+        }
+        Some(path) => run_file(path, args),
+    }
+}
+
+// Reads and runs a line at a time from input until it ends, writing prompts
+// and values to out
+fn repl(mut input: impl BufRead, out: &mut impl Write) {
     let mut persistent_state = Scope::base();
 
     loop {
-        println!("Enter lisp: ");
+        writeln!(out, "Enter lisp: ").ok();
         let mut to_exec = String::new();
-        io::stdin()
-            .read_line(&mut to_exec)
-            .expect("Failed to read line");
+        match input.read_line(&mut to_exec) {
+            // End of input, so there's nothing left to run
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Failed to read line: {}", e);
+                break;
+            }
+        }
 
-        let res: Option<Result>;
+        let cleaned = to_exec.strip_suffix("\n").unwrap_or(&to_exec);
+        let res: RiskResult<Option<Result>> = token::tokenize(cleaned)
+            .and_then(ast::new)
+            .and_then(|exprs| exec::exec(exprs, Some(&mut persistent_state)));
 
-        if let Some(cleaned) = to_exec.strip_suffix("\n") {
-            res = exec::exec(
-                ast::new(token::tokenize(cleaned)),
-                Some(&mut persistent_state),
-            );
-        } else {
-            res = exec::exec(
-                ast::new(token::tokenize(&to_exec)),
-                Some(&mut persistent_state),
-            );
+        match res {
+            Ok(Some(r)) => writeln!(out, "{}", r),
+            Ok(None) => writeln!(out),
+            Err(e) => {
+                report(e);
+                Ok(())
+            }
         }
+        .ok();
+    }
+}
 
-        match res {
-            Some(r) => println!("{}", r),
-            None => println!(""),
+// Runs the file at path, returning the exit code. args are the script's path
+// followed by its arguments
+fn run_file(path: &str, args: &[String]) -> i32 {
+    match fs::read_to_string(path) {
+        Ok(source) => run(&skip_shebang(&source), path, args, None),
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path, e);
+            1
+        }
+    }
+}
+
+// Runs a program read from stdin without prompting for it, printing the value
+// of its last form
+fn run_stdin(args: &[String]) -> i32 {
+    let mut source = String::new();
+    match io::stdin().read_to_string(&mut source) {
+        Ok(_) => run(
+            &skip_shebang(&source),
+            "<stdin>",
+            args,
+            Some(&mut io::stdout()),
+        ),
+        Err(e) => {
+            eprintln!("Could not read stdin: {}", e);
+            1
+        }
+    }
+}
+
+// Runs every form of a program, returning the exit code. file names the
+// program in error messages, and the last form's value is written to out if
+// there is one. args are where the program came from followed by its
+// arguments, which command-line returns
+fn run(source: &str, file: &str, args: &[String], out: Option<&mut dyn Write>) -> i32 {
+    // command-line is a function returning the arguments, which are kept under
+    // a name that can't be written in source so they can't be changed
+    let mut scope = Scope::base();
+    scope.define(
+        " command-line",
+        pair::list(
+            args.iter()
+                .map(|arg| Result::Primitive(Primitive::S(arg.to_string())))
+                .collect(),
+        ),
+    );
+    let command_line = FnDef::new(vec![], vec![AstNode::leaf(" command-line")], scope.clone());
+    scope.define(
+        "command-line",
+        Result::FnDef(command_line.named("command-line")),
+    );
+
+    let res = token::tokenize_file(source, file)
+        .and_then(ast::new)
+        .and_then(|exprs| exec::exec(exprs, Some(&mut scope)));
+
+    match (res, out) {
+        (Ok(Some(r)), Some(out)) => {
+            writeln!(out, "{}", r).ok();
+            0
+        }
+        (Ok(_), _) => 0,
+        (Err(e), _) => {
+            report(e);
+            1
+        }
+    }
+}
+
+// Blanks out a #! line at the start of a script so it can be run directly.
+// Spaces are left in its place so locations in the rest of the file stay right
+fn skip_shebang(source: &str) -> String {
+    match source.strip_prefix("#!") {
+        Some(_) => {
+            let end = source.find('\n').unwrap_or(source.len());
+            " ".repeat(end) + &source[end..]
         }
+        None => source.to_string(),
+    }
+}
+
+// Prints an uncaught error, with the calls it unwound through
+fn report(e: RiskError) {
+    eprintln!("{}", e);
+
+    let frames = trace::take();
+    if !frames.is_empty() {
+        eprintln!("{}", trace::backtrace(&frames));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebang_lines_are_blanked() {
+        assert_eq!(
+            skip_shebang("#!/usr/bin/env risk\n(+ 1 2)"),
+            "                   \n(+ 1 2)"
+        );
+        assert_eq!(skip_shebang("#!risk"), "      ");
+        assert_eq!(skip_shebang("(+ 1 2)"), "(+ 1 2)");
+    }
+
+    #[test]
+    fn programs_exit_non_zero_on_error() {
+        let args = ["-".to_string()];
+
+        assert_eq!(run("(define x 1) (+ x 1)", "<stdin>", &args, None), 0);
+        assert_eq!(run("(car 1)", "<stdin>", &args, None), 1);
+        assert_eq!(run("(", "<stdin>", &args, None), 1);
+        assert_eq!(
+            run(
+                "(if (eq? (car (command-line)) \"-\") 1 (car 1))",
+                "<stdin>",
+                &args,
+                None
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn programs_can_print_their_last_value() {
+        let args = ["<eval>".to_string()];
+        let mut out = Vec::new();
+
+        assert_eq!(
+            run("(define x 2) (* x 21)", "<eval>", &args, Some(&mut out)),
+            0
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), "42\n");
+
+        let mut out = Vec::new();
+        assert_eq!(run("(define x 2)", "<eval>", &args, Some(&mut out)), 0);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn repl_stops_at_the_end_of_input() {
+        let mut out = Vec::new();
+        repl("(define x 2)\n(* x 21)\n".as_bytes(), &mut out);
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Enter lisp: \n\nEnter lisp: \n42\nEnter lisp: \n"
+        );
     }
 }